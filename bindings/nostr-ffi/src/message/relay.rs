@@ -14,6 +14,10 @@ pub enum RelayMessage {
     EndOfStoredEvents {
         subscription_id: String,
     },
+    Closed {
+        subscription_id: String,
+        message: String,
+    },
     Ok {
         event_id: String,
         status: bool,
@@ -42,6 +46,13 @@ impl From<NRelayMessage> for RelayMessage {
             NRelayMessage::EndOfStoredEvents(sub_id) => Self::EndOfStoredEvents {
                 subscription_id: sub_id.to_string(),
             },
+            NRelayMessage::Closed {
+                subscription_id,
+                message,
+            } => Self::Closed {
+                subscription_id: subscription_id.to_string(),
+                message,
+            },
             NRelayMessage::Ok {
                 event_id,
                 status,
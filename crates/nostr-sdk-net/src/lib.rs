@@ -11,6 +11,6 @@ pub mod native;
 pub mod wasm;
 
 #[cfg(not(target_arch = "wasm32"))]
-pub use self::native::Message as WsMessage;
+pub use self::native::{ConnectionConfig, IpPreference, Message as WsMessage, Resolver};
 #[cfg(target_arch = "wasm32")]
 pub use ws_stream_wasm::WsMessage;
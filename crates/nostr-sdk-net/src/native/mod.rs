@@ -3,25 +3,37 @@
 
 //! Native Network
 
-use std::net::SocketAddr;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use futures_util::future::BoxFuture;
+use futures_util::stream::FuturesUnordered;
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::StreamExt;
 use thiserror::Error;
 use tokio::net::TcpStream;
 use tokio_rustls::client::TlsStream;
-use tokio_rustls::rustls::{ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::rustls::client::{ServerCertVerified, ServerCertVerifier};
+use tokio_rustls::rustls::{
+    Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+};
 use tokio_rustls::TlsConnector;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::header::{HeaderName, HeaderValue};
 use tokio_tungstenite::tungstenite::Error as WsError;
 pub use tokio_tungstenite::tungstenite::Message;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
-use url::{ParseError, Url};
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+use url::{Host, ParseError, Url};
 
 type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
-type Sink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
-type Stream = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+/// Write half of the WebSocket
+pub type Sink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+/// Read half of the WebSocket
+pub type Stream = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
 
 mod socks;
 
@@ -34,7 +46,7 @@ pub enum Error {
     IO(#[from] std::io::Error),
     /// Ws error
     #[error("ws error: {0}")]
-    Ws(#[from] WsError),
+    Ws(Box<WsError>),
     #[error("socks error: {0}")]
     Socks(#[from] tokio_socks::Error),
     /// Timeout
@@ -46,34 +58,290 @@ pub enum Error {
     /// Url parse error
     #[error("impossible to parse URL: {0}")]
     Url(#[from] url::ParseError),
+    /// No address available for the requested IP version
+    #[error("no address available for the requested IP version")]
+    NoAddressAvailable,
+    /// Invalid root certificate
+    #[error("invalid root certificate: {0}")]
+    InvalidCertificate(String),
+    /// Invalid handshake header
+    #[error("invalid header: {0}")]
+    InvalidHeader(String),
 }
 
+impl From<WsError> for Error {
+    fn from(e: WsError) -> Self {
+        Self::Ws(Box::new(e))
+    }
+}
+
+/// Connection configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionConfig {
+    /// Connection timeout (default: 60 secs)
+    pub timeout: Option<Duration>,
+    /// Verify the TLS certificate of the relay (default: true)
+    ///
+    /// Disable it only for local test relays: the connection becomes vulnerable to MITM attacks.
+    pub tls_verification: bool,
+    /// Additional DER-encoded root certificates to trust (default: empty)
+    ///
+    /// Useful for self-hosted relays signed by a private CA.
+    pub root_certificates: Vec<Vec<u8>>,
+    /// Additional HTTP headers of the WebSocket handshake request (default: empty)
+    ///
+    /// I.e. the `Authorization` required by an authenticating reverse proxy.
+    pub headers: HashMap<String, String>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            tls_verification: true,
+            root_certificates: Vec::new(),
+            headers: HashMap::new(),
+        }
+    }
+}
+
+impl ConnectionConfig {
+    /// New default [`ConnectionConfig`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set connection timeout
+    pub fn timeout(self, timeout: Option<Duration>) -> Self {
+        Self { timeout, ..self }
+    }
+
+    /// Enable or disable TLS certificate verification
+    pub fn tls_verification(self, tls_verification: bool) -> Self {
+        Self {
+            tls_verification,
+            ..self
+        }
+    }
+
+    /// Trust an additional DER-encoded root certificate
+    pub fn add_root_certificate(mut self, der: Vec<u8>) -> Self {
+        self.root_certificates.push(der);
+        self
+    }
+
+    /// Add an HTTP header to the WebSocket handshake request
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    fn get_timeout(&self) -> Duration {
+        self.timeout.unwrap_or(Duration::from_secs(60))
+    }
+
+    fn is_custom_tls(&self) -> bool {
+        !self.tls_verification || !self.root_certificates.is_empty()
+    }
+}
+
+/// Accept any server certificate
+struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// Delay before starting the connection attempt to the next address (RFC 8305)
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// IP version preference
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IpPreference {
+    /// Use both IPv4 and IPv6 addresses (default)
+    ///
+    /// The addresses of the two families are interleaved and raced ("happy eyeballs", RFC 8305):
+    /// an unreachable address doesn't use up the whole connection timeout.
+    #[default]
+    Both,
+    /// Use only IPv4 addresses
+    V4Only,
+    /// Use only IPv6 addresses
+    V6Only,
+}
+
+impl IpPreference {
+    fn allows(&self, addr: &IpAddr) -> bool {
+        match self {
+            Self::Both => true,
+            Self::V4Only => addr.is_ipv4(),
+            Self::V6Only => addr.is_ipv6(),
+        }
+    }
+}
+
+/// Custom DNS resolver
+///
+/// Replace the system resolver for the direct connections (i.e. to resolve via DNS-over-HTTPS or a specific server).
+pub trait Resolver: fmt::Debug + Send + Sync {
+    /// Resolve the host to the socket addresses to dial, in order of preference
+    fn resolve<'a>(
+        &'a self,
+        host: &'a str,
+        port: u16,
+    ) -> BoxFuture<'a, Result<Vec<SocketAddr>, std::io::Error>>;
+}
+
+/// Connect to relay
+///
+/// The [`IpPreference`] and the [`Resolver`] are ignored when a proxy is used, since the proxy resolves the host
+/// (SOCKS5 remote DNS): no DNS query is made locally.
 pub async fn connect(
     url: &Url,
     proxy: Option<SocketAddr>,
-    timeout: Option<Duration>,
+    config: &ConnectionConfig,
+    ip_preference: IpPreference,
+    resolver: Option<&dyn Resolver>,
 ) -> Result<(Sink, Stream), Error> {
+    // Build the handshake request, with the custom headers
+    let mut request: Request = url.as_str().into_client_request()?;
+    for (key, value) in config.headers.iter() {
+        let name = HeaderName::from_bytes(key.as_bytes())
+            .map_err(|e| Error::InvalidHeader(e.to_string()))?;
+        let value =
+            HeaderValue::from_str(value).map_err(|e| Error::InvalidHeader(e.to_string()))?;
+        request.headers_mut().insert(name, value);
+    }
+
     let stream = match proxy {
-        Some(proxy) => connect_proxy(url, proxy, timeout).await?,
-        None => connect_direct(url, timeout).await?,
+        Some(proxy) => connect_proxy(url, request, proxy, config).await?,
+        None => connect_direct(url, request, config, ip_preference, resolver).await?,
     };
     Ok(stream.split())
 }
 
-async fn connect_direct(url: &Url, timeout: Option<Duration>) -> Result<WebSocket, Error> {
-    let timeout = timeout.unwrap_or(Duration::from_secs(60));
-    let (stream, _) = tokio::time::timeout(timeout, tokio_tungstenite::connect_async(url))
-        .await
-        .map_err(|_| Error::Timeout)??;
+async fn connect_direct(
+    url: &Url,
+    request: Request,
+    config: &ConnectionConfig,
+    ip_preference: IpPreference,
+    resolver: Option<&dyn Resolver>,
+) -> Result<WebSocket, Error> {
+    let timeout = config.get_timeout();
+    let connector = if config.is_custom_tls() {
+        Some(Connector::Rustls(tls_config(config)?))
+    } else {
+        None
+    };
+    let (stream, _) = tokio::time::timeout(timeout, async {
+        let conn = connect_tcp(url, ip_preference, resolver).await?;
+        Ok::<_, Error>(
+            tokio_tungstenite::client_async_tls_with_config(request, conn, None, connector).await?,
+        )
+    })
+    .await
+    .map_err(|_| Error::Timeout)??;
     Ok(stream)
 }
 
+/// Resolve the host (with the system resolver, if no [`Resolver`] is passed) and
+/// dial the addresses allowed by the [`IpPreference`], returning the first connected
+///
+/// The addresses are interleaved by family and a new attempt is started every [`CONNECTION_ATTEMPT_DELAY`],
+/// or as soon as the previous one fails, without cancelling the pending ones.
+async fn connect_tcp(
+    url: &Url,
+    ip_preference: IpPreference,
+    resolver: Option<&dyn Resolver>,
+) -> Result<TcpStream, Error> {
+    let port: u16 = url
+        .port_or_known_default()
+        .ok_or(Error::Url(ParseError::InvalidPort))?;
+    let addrs: Vec<SocketAddr> = match url.host().ok_or(Error::Url(ParseError::EmptyHost))? {
+        Host::Domain(domain) => match resolver {
+            Some(resolver) => resolver.resolve(domain, port).await?,
+            None => tokio::net::lookup_host((domain, port)).await?.collect(),
+        },
+        Host::Ipv4(ip) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Host::Ipv6(ip) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+    };
+    let addrs: Vec<SocketAddr> = interleave(
+        addrs
+            .into_iter()
+            .filter(|addr| ip_preference.allows(&addr.ip()))
+            .collect(),
+    );
+
+    let mut pending = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_error: Option<std::io::Error> = None;
+    loop {
+        if let Some(addr) = pending.next() {
+            attempts.push(TcpStream::connect(addr));
+        }
+
+        let res = if pending.as_slice().is_empty() {
+            attempts.next().await
+        } else {
+            match tokio::time::timeout(CONNECTION_ATTEMPT_DELAY, attempts.next()).await {
+                Ok(res) => res,
+                // Still pending: start the next attempt
+                Err(_) => continue,
+            }
+        };
+
+        match res {
+            Some(Ok(stream)) => return Ok(stream),
+            Some(Err(e)) => last_error = Some(e),
+            None => break,
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(Error::IO(e)),
+        None => Err(Error::NoAddressAvailable),
+    }
+}
+
+/// Alternate IPv6 and IPv4 addresses, starting with the family of the first one
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6: bool = addrs.first().map(|addr| addr.is_ipv6()).unwrap_or(false);
+    let (mut preferred, mut others): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_v6);
+    let mut interleaved: Vec<SocketAddr> = Vec::with_capacity(preferred.len() + others.len());
+    preferred.reverse();
+    others.reverse();
+    loop {
+        match (preferred.pop(), others.pop()) {
+            (None, None) => break,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+    interleaved
+}
+
 async fn connect_proxy(
     url: &Url,
+    request: Request,
     proxy: SocketAddr,
-    timeout: Option<Duration>,
+    config: &ConnectionConfig,
 ) -> Result<WebSocket, Error> {
-    let timeout = timeout.unwrap_or(Duration::from_secs(60));
+    let timeout = config.get_timeout();
     let addr: String = match url.host_str() {
         Some(host) => match url.port_or_known_default() {
             Some(port) => format!("{host}:{port}"),
@@ -82,22 +350,33 @@ async fn connect_proxy(
         None => return Err(Error::Url(ParseError::InvalidPort)),
     };
 
-    let conn = TpcSocks5Stream::connect(proxy, addr.clone()).await?;
-    let conn = match connect_with_tls(conn, url).await {
-        Ok(stream) => MaybeTlsStream::Rustls(stream),
-        Err(_) => {
-            let conn = TpcSocks5Stream::connect(proxy, addr).await?;
-            MaybeTlsStream::Plain(conn)
-        }
+    // Never downgrade `wss` to plaintext: TLS and certificate errors are propagated
+    let conn = TpcSocks5Stream::connect(proxy, addr).await?;
+    let conn = if url.scheme() == "wss" {
+        MaybeTlsStream::Rustls(connect_with_tls(conn, url, config).await?)
+    } else {
+        MaybeTlsStream::Plain(conn)
     };
 
-    let (stream, _) = tokio::time::timeout(timeout, tokio_tungstenite::client_async(url, conn))
+    let (stream, _) = tokio::time::timeout(timeout, tokio_tungstenite::client_async(request, conn))
         .await
         .map_err(|_| Error::Timeout)??;
     Ok(stream)
 }
 
-async fn connect_with_tls(stream: TcpStream, url: &Url) -> Result<TlsStream<TcpStream>, Error> {
+async fn connect_with_tls(
+    stream: TcpStream,
+    url: &Url,
+    config: &ConnectionConfig,
+) -> Result<TlsStream<TcpStream>, Error> {
+    let connector = TlsConnector::from(tls_config(config)?);
+    let domain = url.domain().ok_or(Error::InvalidDNSName)?;
+    let domain = ServerName::try_from(domain).map_err(|_| Error::InvalidDNSName)?;
+    Ok(connector.connect(domain, stream).await?)
+}
+
+/// Build the rustls [`ClientConfig`]: webpki roots plus the custom ones
+fn tls_config(config: &ConnectionConfig) -> Result<Arc<ClientConfig>, Error> {
     let mut root_cert_store = RootCertStore::empty();
     root_cert_store.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
         OwnedTrustAnchor::from_subject_spki_name_constraints(
@@ -106,12 +385,58 @@ async fn connect_with_tls(stream: TcpStream, url: &Url) -> Result<TlsStream<TcpS
             ta.name_constraints,
         )
     }));
-    let config = ClientConfig::builder()
+    for der in config.root_certificates.iter() {
+        root_cert_store
+            .add(&Certificate(der.clone()))
+            .map_err(|e| Error::InvalidCertificate(e.to_string()))?;
+    }
+    let mut tls = ClientConfig::builder()
         .with_safe_defaults()
         .with_root_certificates(root_cert_store)
         .with_no_client_auth();
-    let connector = TlsConnector::from(Arc::new(config));
-    let domain = url.domain().ok_or(Error::InvalidDNSName)?;
-    let domain = ServerName::try_from(domain).map_err(|_| Error::InvalidDNSName)?;
-    Ok(connector.connect(domain, stream).await?)
+    if !config.tls_verification {
+        tls.dangerous()
+            .set_certificate_verifier(Arc::new(NoCertificateVerification));
+    }
+    Ok(Arc::new(tls))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_interleave() {
+        // Starts with the family of the first address, the rest appended at the end
+        assert_eq!(
+            interleave(addrs(&[
+                "[::1]:443",
+                "[::2]:443",
+                "[::3]:443",
+                "127.0.0.1:443",
+                "127.0.0.2:443"
+            ])),
+            addrs(&[
+                "[::1]:443",
+                "127.0.0.1:443",
+                "[::2]:443",
+                "127.0.0.2:443",
+                "[::3]:443"
+            ])
+        );
+        assert_eq!(
+            interleave(addrs(&["127.0.0.1:443", "[::1]:443", "127.0.0.2:443"])),
+            addrs(&["127.0.0.1:443", "[::1]:443", "127.0.0.2:443"])
+        );
+
+        // Single family: order kept
+        let v4 = addrs(&["127.0.0.2:443", "127.0.0.1:443"]);
+        assert_eq!(interleave(v4.clone()), v4);
+
+        assert!(interleave(Vec::new()).is_empty());
+    }
 }
@@ -18,7 +18,7 @@ use tokio::sync::broadcast;
 #[cfg(feature = "nip46")]
 use super::signer::remote::RemoteSigner;
 use super::{Entity, Error, Options, TryIntoUrl};
-use crate::relay::{pool, Relay, RelayOptions, RelayPoolNotification};
+use crate::relay::{pool, recv_notification, Relay, RelayOptions, RelayPoolNotification};
 use crate::RUNTIME;
 
 #[derive(Debug, Clone)]
@@ -431,7 +431,7 @@ impl Client {
         F: Fn(RelayPoolNotification) -> Result<bool>,
     {
         let mut notifications = self.client.notifications();
-        while let Ok(notification) = RUNTIME.block_on(notifications.recv()) {
+        while let Some(notification) = RUNTIME.block_on(recv_notification(&mut notifications)) {
             let stop: bool = RelayPoolNotification::Stop == notification;
             let shutdown: bool = RelayPoolNotification::Shutdown == notification;
             let exit: bool = func(notification).map_err(|e| Error::Handler(e.to_string()))?;
@@ -36,7 +36,9 @@ pub use self::options::Options;
 #[cfg(feature = "nip46")]
 pub use self::signer::remote::RemoteSigner;
 use crate::relay::pool::{self, Error as RelayPoolError, RelayPool};
-use crate::relay::{FilterOptions, Relay, RelayOptions, RelayPoolNotification, RelaySendOptions};
+use crate::relay::{
+    recv_notification, FilterOptions, Relay, RelayOptions, RelayPoolNotification, RelaySendOptions,
+};
 use crate::util::TryIntoUrl;
 
 /// [`Client`] error
@@ -1301,7 +1303,7 @@ impl Client {
         Fut: Future<Output = Result<bool>>,
     {
         let mut notifications = self.notifications();
-        while let Ok(notification) = notifications.recv().await {
+        while let Some(notification) = recv_notification(&mut notifications).await {
             let stop: bool = RelayPoolNotification::Stop == notification;
             let shutdown: bool = RelayPoolNotification::Shutdown == notification;
             let exit: bool = func(notification)
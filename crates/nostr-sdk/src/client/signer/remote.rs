@@ -19,7 +19,7 @@ use tokio::sync::Mutex;
 #[cfg(feature = "blocking")]
 use crate::client::blocking::Client as BlockingClient;
 use crate::client::{Client, Error};
-use crate::relay::RelayPoolNotification;
+use crate::relay::{recv_notification, RelayPoolNotification};
 #[cfg(feature = "blocking")]
 use crate::RUNTIME;
 
@@ -124,7 +124,7 @@ impl Client {
 
             let mut notifications = self.notifications();
             time::timeout(timeout, async {
-                while let Ok(notification) = notifications.recv().await {
+                while let Some(notification) = recv_notification(&mut notifications).await {
                     if let RelayPoolNotification::Event(_url, event) = notification {
                         if event.kind == Kind::NostrConnect {
                             let msg: String = nip04::decrypt(
@@ -192,7 +192,7 @@ impl Client {
 
         let mut notifications = self.notifications();
         let future = async {
-            while let Ok(notification) = notifications.recv().await {
+            while let Some(notification) = recv_notification(&mut notifications).await {
                 if let RelayPoolNotification::Event(_url, event) = notification {
                     if event.kind == Kind::NostrConnect {
                         let msg = nip04::decrypt(
@@ -31,8 +31,10 @@ pub mod util;
 pub use self::client::blocking;
 pub use self::client::{Client, Options};
 pub use self::relay::{
-    ActiveSubscription, FilterOptions, InternalSubscriptionId, Relay, RelayConnectionStats,
-    RelayOptions, RelayPoolNotification, RelayPoolOptions, RelaySendOptions, RelayStatus,
+    ActiveSubscription, CancelHandle, DisabledPolicy, FilterOptions, InternalSubscriptionId,
+    QueueOverflowPolicy, Relay, RelayConnectionStats, RelayOptions, RelayOptionsBuilder,
+    RelayPoolNotification, RelayPoolOptions, RelaySendOptions, RelaySnapshot, RelayStatus,
+    ScoringWeights, SendConfirmation, SendEventOutput, SubscribeOptions,
 };
 
 #[cfg(feature = "blocking")]
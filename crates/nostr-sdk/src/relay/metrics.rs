@@ -0,0 +1,196 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Metrics in Prometheus text format
+
+use std::fmt::Write;
+
+use super::{RelaySnapshot, RelayStatus};
+
+const STATUSES: [RelayStatus; 6] = [
+    RelayStatus::Initialized,
+    RelayStatus::Connected,
+    RelayStatus::Connecting,
+    RelayStatus::Disconnected,
+    RelayStatus::Stopped,
+    RelayStatus::Terminated,
+];
+
+/// Metric name, help and value
+type Metric = (&'static str, &'static str, fn(&RelaySnapshot) -> f64);
+
+/// Render the snapshots in Prometheus exposition format, with the relay url as `url` label
+pub(crate) fn render(snapshots: &[RelaySnapshot]) -> String {
+    let mut output = String::new();
+
+    let counters: [Metric; 4] = [
+        (
+            "nostr_relay_connection_attempts_total",
+            "Connection attempts",
+            |s| s.attempts as f64,
+        ),
+        (
+            "nostr_relay_connection_successes_total",
+            "Successful connections",
+            |s| s.success as f64,
+        ),
+        ("nostr_relay_bytes_sent_total", "Bytes sent", |s| {
+            s.bytes_sent as f64
+        }),
+        ("nostr_relay_bytes_received_total", "Bytes received", |s| {
+            s.bytes_received as f64
+        }),
+    ];
+    let gauges: [Metric; 3] = [
+        (
+            "nostr_relay_latency_seconds",
+            "Latency between a REQ and its first EVENT or EOSE (zero if not measured yet)",
+            |s| s.latency.as_secs_f64(),
+        ),
+        (
+            "nostr_relay_queue_depth",
+            "Outgoing messages waiting in the queue",
+            |s| s.queue as f64,
+        ),
+        (
+            "nostr_relay_subscriptions",
+            "Number of active subscriptions",
+            |s| s.subscriptions as f64,
+        ),
+    ];
+
+    for (kind, metrics) in [("counter", &counters[..]), ("gauge", &gauges[..])] {
+        for (name, help, value) in metrics.iter() {
+            let _ = writeln!(output, "# HELP {name} {help}");
+            let _ = writeln!(output, "# TYPE {name} {kind}");
+            for snapshot in snapshots.iter() {
+                let url = escape(snapshot.url.as_str());
+                let _ = writeln!(output, "{name}{{url=\"{url}\"}} {}", value(snapshot));
+            }
+        }
+    }
+
+    let name = "nostr_relay_status";
+    let _ = writeln!(
+        output,
+        "# HELP {name} Connection status (1 for the current one)"
+    );
+    let _ = writeln!(output, "# TYPE {name} gauge");
+    for snapshot in snapshots.iter() {
+        let url = escape(snapshot.url.as_str());
+        for status in STATUSES.iter() {
+            let value = u8::from(status == &snapshot.status);
+            let status = status.to_string().to_lowercase();
+            let _ = writeln!(
+                output,
+                "{name}{{url=\"{url}\",status=\"{status}\"}} {value}"
+            );
+        }
+    }
+
+    output
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nostr::Url;
+
+    use super::*;
+
+    fn snapshot(url: &str, status: RelayStatus) -> RelaySnapshot {
+        RelaySnapshot {
+            url: Url::parse(url).unwrap(),
+            status,
+            #[cfg(not(target_arch = "wasm32"))]
+            proxy: None,
+            attempts: 0,
+            success: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            latency: Duration::ZERO,
+            queue: 0,
+            subscriptions: 0,
+            #[cfg(feature = "nip11")]
+            supported_nips: None,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let first = RelaySnapshot {
+            attempts: 2,
+            success: 1,
+            bytes_sent: 10,
+            bytes_received: 20,
+            latency: Duration::from_millis(250),
+            queue: 4,
+            subscriptions: 3,
+            ..snapshot("wss://relay1.example.com", RelayStatus::Connected)
+        };
+        let second = snapshot("wss://relay2.example.com/nostr", RelayStatus::Stopped);
+
+        let expected = r#"# HELP nostr_relay_connection_attempts_total Connection attempts
+# TYPE nostr_relay_connection_attempts_total counter
+nostr_relay_connection_attempts_total{url="wss://relay1.example.com/"} 2
+nostr_relay_connection_attempts_total{url="wss://relay2.example.com/nostr"} 0
+# HELP nostr_relay_connection_successes_total Successful connections
+# TYPE nostr_relay_connection_successes_total counter
+nostr_relay_connection_successes_total{url="wss://relay1.example.com/"} 1
+nostr_relay_connection_successes_total{url="wss://relay2.example.com/nostr"} 0
+# HELP nostr_relay_bytes_sent_total Bytes sent
+# TYPE nostr_relay_bytes_sent_total counter
+nostr_relay_bytes_sent_total{url="wss://relay1.example.com/"} 10
+nostr_relay_bytes_sent_total{url="wss://relay2.example.com/nostr"} 0
+# HELP nostr_relay_bytes_received_total Bytes received
+# TYPE nostr_relay_bytes_received_total counter
+nostr_relay_bytes_received_total{url="wss://relay1.example.com/"} 20
+nostr_relay_bytes_received_total{url="wss://relay2.example.com/nostr"} 0
+# HELP nostr_relay_latency_seconds Latency between a REQ and its first EVENT or EOSE (zero if not measured yet)
+# TYPE nostr_relay_latency_seconds gauge
+nostr_relay_latency_seconds{url="wss://relay1.example.com/"} 0.25
+nostr_relay_latency_seconds{url="wss://relay2.example.com/nostr"} 0
+# HELP nostr_relay_queue_depth Outgoing messages waiting in the queue
+# TYPE nostr_relay_queue_depth gauge
+nostr_relay_queue_depth{url="wss://relay1.example.com/"} 4
+nostr_relay_queue_depth{url="wss://relay2.example.com/nostr"} 0
+# HELP nostr_relay_subscriptions Number of active subscriptions
+# TYPE nostr_relay_subscriptions gauge
+nostr_relay_subscriptions{url="wss://relay1.example.com/"} 3
+nostr_relay_subscriptions{url="wss://relay2.example.com/nostr"} 0
+# HELP nostr_relay_status Connection status (1 for the current one)
+# TYPE nostr_relay_status gauge
+nostr_relay_status{url="wss://relay1.example.com/",status="initialized"} 0
+nostr_relay_status{url="wss://relay1.example.com/",status="connected"} 1
+nostr_relay_status{url="wss://relay1.example.com/",status="connecting"} 0
+nostr_relay_status{url="wss://relay1.example.com/",status="disconnected"} 0
+nostr_relay_status{url="wss://relay1.example.com/",status="stopped"} 0
+nostr_relay_status{url="wss://relay1.example.com/",status="terminated"} 0
+nostr_relay_status{url="wss://relay2.example.com/nostr",status="initialized"} 0
+nostr_relay_status{url="wss://relay2.example.com/nostr",status="connected"} 0
+nostr_relay_status{url="wss://relay2.example.com/nostr",status="connecting"} 0
+nostr_relay_status{url="wss://relay2.example.com/nostr",status="disconnected"} 0
+nostr_relay_status{url="wss://relay2.example.com/nostr",status="stopped"} 1
+nostr_relay_status{url="wss://relay2.example.com/nostr",status="terminated"} 0
+"#;
+        assert_eq!(render(&[first, second]), expected);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("wss://relay.example.com/"),
+            "wss://relay.example.com/"
+        );
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -3,33 +3,73 @@
 
 //! Relay
 
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 #[cfg(not(target_arch = "wasm32"))]
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 
+use async_utility::futures_util::stream::FuturesUnordered;
 use async_utility::{futures_util, thread, time};
+#[cfg(target_arch = "wasm32")]
+use instant::Instant;
+use nostr::event::builder::Error as EventBuilderError;
+use nostr::key::XOnlyPublicKey;
 use nostr::message::MessageHandleError;
 #[cfg(feature = "nip11")]
 use nostr::nips::nip11::RelayInformationDocument;
-use nostr::{ClientMessage, Event, EventId, Filter, RelayMessage, SubscriptionId, Timestamp, Url};
-use nostr_sdk_net::futures_util::{Future, SinkExt, StreamExt};
+#[cfg(feature = "nip11")]
+use nostr::nips::nip13;
+use nostr::secp256k1::rand;
+use nostr::{
+    ClientMessage, Event, EventBuilder, EventId, Filter, Keys, Kind, RelayMessage, SubscriptionId,
+    Tag, Timestamp, Url,
+};
+use nostr_sdk_net::futures_util::{Future, SinkExt, Stream, StreamExt};
 use nostr_sdk_net::{self as net, WsMessage};
+use serde::Serialize;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::{self, Receiver, Sender};
-use tokio::sync::{broadcast, oneshot, Mutex};
+use tokio::sync::{broadcast, oneshot, watch, Mutex, Notify, OwnedSemaphorePermit, Semaphore};
 
+#[cfg(feature = "metrics")]
+mod metrics;
 mod options;
 pub mod pool;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod transport;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use nostr_sdk_net::ConnectionConfig;
+#[cfg(not(target_arch = "wasm32"))]
+pub use nostr_sdk_net::IpPreference;
+#[cfg(not(target_arch = "wasm32"))]
+pub use nostr_sdk_net::Resolver;
 
-pub use self::options::{FilterOptions, RelayOptions, RelayPoolOptions, RelaySendOptions};
+pub use self::options::{
+    DisabledPolicy, FilterOptions, MessageSerializer, QueueOverflowPolicy, RawMessageHook,
+    RelayOptions, RelayOptionsBuilder, RelayPoolOptions, RelaySendOptions, ScoringWeights,
+    SendConfirmation, SubscribeOptions,
+};
 pub use self::pool::{RelayPoolMessage, RelayPoolNotification};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::transport::Transport;
+#[cfg(not(target_arch = "wasm32"))]
+use self::transport::{WsSink, WsStream};
+use crate::util::normalize_url;
 #[cfg(feature = "blocking")]
 use crate::RUNTIME;
 
-type Message = (RelayEvent, Option<oneshot::Sender<bool>>);
+type Message = (RelayEvent, Option<oneshot::Sender<bool>>, Timestamp);
+
+/// Channels waiting for the `OK` (status and message) of an event
+type OkSenders = HashMap<EventId, Vec<oneshot::Sender<(bool, String)>>>;
 
 /// [`Relay`] error
 #[derive(Debug, thiserror::Error)]
@@ -49,13 +89,22 @@ pub enum Error {
     /// Event not published
     #[error("event not published: {0}")]
     EventNotPublished(String),
+    /// No rate limit token available in time
+    #[error("rate limited")]
+    RateLimited,
+    /// Plaintext `ws://` connection not allowed
+    #[error("insecure connection not allowed: {0}")]
+    InsecureConnection(Url),
+    /// The relay sent a `NOTICE` about the current operation
+    #[error("relay notice: {0}")]
+    Notice(String),
     /// No event is published
     #[error("events not published: {0:?}")]
     EventsNotPublished(HashMap<EventId, String>),
     /// Only some events
     #[error("partial publish: published={}, others={}", published.len(), not_published.len())]
     PartialPublish {
-        /// Published events
+        /// Published events, in the same order they were submitted
         published: Vec<EventId>,
         /// Not published events
         not_published: HashMap<EventId, String>,
@@ -81,10 +130,65 @@ pub enum Error {
     /// Filters empty
     #[error("filters empty")]
     FiltersEmpty,
+    /// Subscription ID already used by another subscription
+    #[error("subscription ID already in use: {0}")]
+    SubscriptionIdCollision(SubscriptionId),
+    /// [`EventBuilder`] error
+    #[error("event builder error: {0}")]
+    EventBuilder(#[from] EventBuilderError),
+    /// No `AUTH` challenge received from the relay
+    #[error("auth challenge not received")]
+    AuthChallengeNotFound,
+    /// Authentication rejected by the relay
+    #[error("auth failed: {0}")]
+    AuthFailed(String),
+    /// Request exceeds the limitations advertised by the relay (NIP-11)
+    #[error("limitation exceeded: {0}")]
+    LimitationExceeded(String),
+    /// Too many subscriptions (NIP-11 `max_subscriptions`)
+    #[error("too many subscriptions: max {0}")]
+    TooManySubscriptions(usize),
+    /// Connection closed by the relay
+    #[error("connection closed by relay: code={code}, reason={reason}")]
+    ClosedByRelay {
+        /// Close code
+        code: u16,
+        /// Close reason
+        reason: String,
+    },
+    /// Relay disconnected during the operation
+    #[error("relay disconnected: {0}")]
+    Disconnected(DisconnectReason),
+    /// Subscription closed by the relay with a `CLOSED` message
+    #[error("subscription closed by relay: {reason}")]
+    SubscriptionClosed {
+        /// Reason (i.e. `auth-required: ...`)
+        reason: String,
+    },
+    /// NIP not supported by the relay
+    #[error("NIP-{0} not supported by the relay")]
+    NotSupported(u16),
+    /// Search query empty
+    #[error("search query empty")]
+    SearchQueryEmpty,
+    /// Event PoW difficulty lower than the one required by the relay (NIP-11 `min_pow_difficulty`)
+    #[error("insufficient PoW: required={required}, actual={actual}")]
+    InsufficientPow {
+        /// Required difficulty
+        required: u8,
+        /// Event difficulty
+        actual: u8,
+    },
+    /// Read side of the connection closed: the `OK` messages can't be received
+    #[error("read side of the connection closed: OK messages can't be received")]
+    ReadClosed,
+    /// Kind that can't be probed: publishing it would replace or delete the user's events
+    #[error("kind {0} can't be probed")]
+    KindNotProbeable(Kind),
 }
 
 /// Relay connection status
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
 pub enum RelayStatus {
     /// Relay initialized
     Initialized,
@@ -113,6 +217,71 @@ impl fmt::Display for RelayStatus {
     }
 }
 
+/// Relay disconnection reason
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DisconnectReason {
+    /// Disconnection requested by the user (stop or terminate)
+    UserRequested,
+    /// Connection lost or impossible to establish
+    ConnectionLost,
+    /// Impossible to write to the socket
+    WriteError,
+    /// Close frame received from the relay
+    CloseFrame {
+        /// Close code
+        code: u16,
+        /// Close reason
+        reason: String,
+    },
+    /// Connection up but not receiving data
+    Stale,
+    /// Relay pool dropped: received messages can't be forwarded anymore
+    PoolDropped,
+    /// No pong received in time
+    PingTimeout,
+    /// No activity for [`RelayOptions::idle_timeout`]
+    Idle,
+}
+
+/// Why the `REQ` of a subscription has been sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubscribeReason {
+    /// New subscription
+    Created,
+    /// Filters of an existing subscription changed
+    Updated,
+    /// Subscription sent again by [`Relay::resubscribe_all`] (i.e. after reconnection) or [`Relay::resume_subscription`]
+    Resubscribed,
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UserRequested => write!(f, "user requested"),
+            Self::ConnectionLost => write!(f, "connection lost"),
+            Self::WriteError => write!(f, "write error"),
+            Self::CloseFrame { code, reason } => write!(f, "close frame: {code} {reason}"),
+            Self::Stale => write!(f, "stale"),
+            Self::PoolDropped => write!(f, "pool dropped"),
+            Self::PingTimeout => write!(f, "ping timeout"),
+            Self::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+impl DisconnectReason {
+    /// Get [`Error::ClosedByRelay`] if the connection has been closed by the relay
+    pub fn as_error(&self) -> Option<Error> {
+        match self {
+            Self::CloseFrame { code, reason } => Some(Error::ClosedByRelay {
+                code: *code,
+                reason: reason.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
 /// Relay event
 #[derive(Debug)]
 pub enum RelayEvent {
@@ -120,15 +289,109 @@ pub enum RelayEvent {
     SendMsg(Box<ClientMessage>),
     /// Send multiple messages at once
     Batch(Vec<ClientMessage>),
-    // Ping,
+    /// Ping
+    Ping,
     /// Close
-    Close,
+    Close(DisconnectReason),
     /// Stop
     Stop,
     /// Completely disconnect
     Terminate,
 }
 
+/// Output of [`Relay::send_event_with_output`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendEventOutput {
+    /// Event ID
+    pub id: EventId,
+    /// Message of the relay `OK`
+    pub message: String,
+}
+
+impl SendEventOutput {
+    fn new(id: EventId) -> Self {
+        Self {
+            id,
+            message: String::new(),
+        }
+    }
+}
+
+/// Handle to cancel an in-flight query
+///
+/// Returned by [`Relay::req_events_of`] or passed to [`Relay::get_events_of_cancellable`].
+/// Once cancelled, the query stops waiting for events and the subscription is closed.
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl Default for CancelHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CancelHandle {
+    /// New [`CancelHandle`]
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Cancel the query
+    pub fn cancel(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Check if the query has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    async fn cancelled(&self) {
+        let mut receiver = self.sender.subscribe();
+        while !*receiver.borrow() {
+            if receiver.changed().await.is_err() {
+                futures_util::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Point-in-time summary of a [`Relay`] state
+///
+/// Returned by [`Relay::snapshot`]. Can be serialized (i.e. to JSON) for logs or bug reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelaySnapshot {
+    /// Relay url
+    pub url: Url,
+    /// Connection status
+    pub status: RelayStatus,
+    /// Proxy used by the current (or last) connection
+    #[cfg(not(target_arch = "wasm32"))]
+    pub proxy: Option<SocketAddr>,
+    /// Connection attempts
+    pub attempts: usize,
+    /// Successful connections
+    pub success: usize,
+    /// Bytes sent
+    pub bytes_sent: usize,
+    /// Bytes received
+    pub bytes_received: usize,
+    /// Latency (zero if not measured yet)
+    pub latency: Duration,
+    /// Outgoing messages waiting in the queue
+    pub queue: usize,
+    /// Number of active subscriptions
+    pub subscriptions: usize,
+    /// NIPs advertised in the [`RelayInformationDocument`] (`None` if not available)
+    #[cfg(feature = "nip11")]
+    pub supported_nips: Option<Vec<u16>>,
+}
+
 /// [`Relay`] connection stats
 #[derive(Debug, Clone)]
 pub struct RelayConnectionStats {
@@ -137,6 +400,26 @@ pub struct RelayConnectionStats {
     bytes_sent: Arc<AtomicUsize>,
     bytes_received: Arc<AtomicUsize>,
     connected_at: Arc<AtomicU64>,
+    handshake_duration: Arc<AtomicU64>,
+    consecutive_failures: Arc<AtomicUsize>,
+    latency: Arc<AtomicU64>,
+    pong_at: Arc<AtomicU64>,
+    rejected_events: Arc<AtomicUsize>,
+    filter_mismatches: Arc<AtomicUsize>,
+    oversized_messages: Arc<AtomicUsize>,
+    received_at: Arc<AtomicU64>,
+    sent_at: Arc<AtomicU64>,
+    disconnected_count: Arc<AtomicUsize>,
+    connected: Arc<AtomicBool>,
+    dropped_stale_messages: Arc<AtomicUsize>,
+    received_events: Arc<AtomicUsize>,
+    received_eose: Arc<AtomicUsize>,
+    received_ok: Arc<AtomicUsize>,
+    received_notices: Arc<AtomicUsize>,
+    received_auth: Arc<AtomicUsize>,
+    received_unknown: Arc<AtomicUsize>,
+    cache_hits: Arc<AtomicUsize>,
+    cache_misses: Arc<AtomicUsize>,
 }
 
 impl Default for RelayConnectionStats {
@@ -154,6 +437,26 @@ impl RelayConnectionStats {
             bytes_sent: Arc::new(AtomicUsize::new(0)),
             bytes_received: Arc::new(AtomicUsize::new(0)),
             connected_at: Arc::new(AtomicU64::new(0)),
+            handshake_duration: Arc::new(AtomicU64::new(0)),
+            consecutive_failures: Arc::new(AtomicUsize::new(0)),
+            latency: Arc::new(AtomicU64::new(0)),
+            pong_at: Arc::new(AtomicU64::new(0)),
+            rejected_events: Arc::new(AtomicUsize::new(0)),
+            filter_mismatches: Arc::new(AtomicUsize::new(0)),
+            oversized_messages: Arc::new(AtomicUsize::new(0)),
+            received_at: Arc::new(AtomicU64::new(0)),
+            sent_at: Arc::new(AtomicU64::new(0)),
+            disconnected_count: Arc::new(AtomicUsize::new(0)),
+            connected: Arc::new(AtomicBool::new(false)),
+            dropped_stale_messages: Arc::new(AtomicUsize::new(0)),
+            received_events: Arc::new(AtomicUsize::new(0)),
+            received_eose: Arc::new(AtomicUsize::new(0)),
+            received_ok: Arc::new(AtomicUsize::new(0)),
+            received_notices: Arc::new(AtomicUsize::new(0)),
+            received_auth: Arc::new(AtomicUsize::new(0)),
+            received_unknown: Arc::new(AtomicUsize::new(0)),
+            cache_hits: Arc::new(AtomicUsize::new(0)),
+            cache_misses: Arc::new(AtomicUsize::new(0)),
         }
     }
 
@@ -177,17 +480,188 @@ impl RelayConnectionStats {
         self.bytes_received.load(Ordering::SeqCst)
     }
 
+    /// Get the UNIX timestamp of the last data received
+    pub fn last_received_at(&self) -> Timestamp {
+        Timestamp::from(self.received_at.load(Ordering::SeqCst))
+    }
+
+    /// Get the UNIX timestamp of the last message successfully written to the socket
+    ///
+    /// Compare it with [`RelayConnectionStats::last_received_at`] to detect one-directional stalls.
+    pub fn last_sent_at(&self) -> Timestamp {
+        Timestamp::from(self.sent_at.load(Ordering::SeqCst))
+    }
+
     /// Get the UNIX timestamp of the last started connection
     pub fn connected_at(&self) -> Timestamp {
         Timestamp::from(self.connected_at.load(Ordering::SeqCst))
     }
 
+    /// The number of times the connection dropped (from connected to disconnected)
+    pub fn disconnected_count(&self) -> usize {
+        self.disconnected_count.load(Ordering::SeqCst)
+    }
+
+    /// Duration of the current connection (zero if not connected)
+    pub fn uptime(&self) -> Duration {
+        if self.connected.load(Ordering::SeqCst) {
+            let connected_at: u64 = self.connected_at.load(Ordering::SeqCst);
+            Duration::from_secs(Timestamp::now().as_u64().saturating_sub(connected_at))
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// The number of queued messages dropped because older than [`RelayOptions::max_queued_age`]
+    pub fn dropped_stale_messages(&self) -> usize {
+        self.dropped_stale_messages.load(Ordering::SeqCst)
+    }
+
+    /// The number of `EVENT` messages received
+    pub fn received_events(&self) -> usize {
+        self.received_events.load(Ordering::SeqCst)
+    }
+
+    /// The number of `EOSE` messages received
+    pub fn received_eose(&self) -> usize {
+        self.received_eose.load(Ordering::SeqCst)
+    }
+
+    /// The number of `OK` messages received
+    pub fn received_ok(&self) -> usize {
+        self.received_ok.load(Ordering::SeqCst)
+    }
+
+    /// The number of `NOTICE` messages received
+    ///
+    /// A high rate often means that the relay is rate-limiting or rejecting the requests.
+    pub fn received_notices(&self) -> usize {
+        self.received_notices.load(Ordering::SeqCst)
+    }
+
+    /// The number of `AUTH` messages received
+    pub fn received_auth(&self) -> usize {
+        self.received_auth.load(Ordering::SeqCst)
+    }
+
+    /// The number of received messages that can't be parsed (unknown or malformed)
+    pub fn received_unknown(&self) -> usize {
+        self.received_unknown.load(Ordering::SeqCst)
+    }
+
+    /// The number of received events rejected because of an invalid id or signature
+    pub fn rejected_events(&self) -> usize {
+        self.rejected_events.load(Ordering::SeqCst)
+    }
+
+    /// The number of received events dropped because not matching the filters of their subscription
+    ///
+    /// Counted only if [`RelayOptions::enforce_filter_match`] is enabled.
+    pub fn filter_mismatches(&self) -> usize {
+        self.filter_mismatches.load(Ordering::SeqCst)
+    }
+
+    /// The number of received messages dropped because bigger than [`RelayOptions::max_message_size`]
+    pub fn oversized_messages(&self) -> usize {
+        self.oversized_messages.load(Ordering::SeqCst)
+    }
+
+    /// Last pong received
+    pub fn last_pong_at(&self) -> Timestamp {
+        Timestamp::from(self.pong_at.load(Ordering::SeqCst))
+    }
+
+    /// Duration of the last successful connection handshake
+    ///
+    /// Kept across reconnections, so can be used to prioritize the relays before connecting.
+    /// Zero if the relay has never been connected.
+    pub fn last_handshake_duration(&self) -> Duration {
+        Duration::from_millis(self.handshake_duration.load(Ordering::SeqCst))
+    }
+
+    /// The number of connection attempts failed since the last successful one
+    pub fn consecutive_failures(&self) -> usize {
+        self.consecutive_failures.load(Ordering::SeqCst)
+    }
+
+    /// Moving average of the time between a `REQ` and its first `EVENT` or `EOSE`
+    ///
+    /// Zero if not measured yet.
+    pub fn latency(&self) -> Duration {
+        Duration::from_millis(self.latency.load(Ordering::SeqCst))
+    }
+
+    /// The number of `get_events_of` served from the query cache
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits.load(Ordering::SeqCst)
+    }
+
+    /// The number of `get_events_of` not found in the query cache
+    pub fn cache_misses(&self) -> usize {
+        self.cache_misses.load(Ordering::SeqCst)
+    }
+
+    /// Health score in `0..=1`, computed at `now` (see [`ScoringWeights`] for the formula)
+    ///
+    /// Return 0 if all the weights are zero.
+    pub fn score(&self, weights: &ScoringWeights, now: Timestamp) -> f64 {
+        let attempts: usize = self.attempts();
+        let success: f64 = if attempts > 0 {
+            (self.success() as f64 / attempts as f64).min(1.0)
+        } else {
+            0.0
+        };
+
+        let latency: Duration = self.latency();
+        let latency: f64 = if latency.is_zero() {
+            0.5
+        } else {
+            let reference: f64 = weights.latency_reference.as_secs_f64();
+            reference / (reference + latency.as_secs_f64())
+        };
+
+        let failures: f64 = 1.0 / (1.0 + self.consecutive_failures() as f64);
+
+        let received_at: u64 = self.received_at.load(Ordering::SeqCst);
+        let freshness: f64 = if received_at > 0 {
+            let reference: f64 = weights.freshness_reference.as_secs_f64();
+            let elapsed: f64 = now.as_u64().saturating_sub(received_at) as f64;
+            reference / (reference + elapsed)
+        } else {
+            0.0
+        };
+
+        let total: f64 = weights.success + weights.latency + weights.failures + weights.freshness;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let score: f64 = (weights.success * success
+            + weights.latency * latency
+            + weights.failures * failures
+            + weights.freshness * freshness)
+            / total;
+        if score.is_nan() {
+            0.0
+        } else {
+            score.clamp(0.0, 1.0)
+        }
+    }
+
+    pub(crate) fn new_cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn new_cache_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::SeqCst);
+    }
+
     pub(crate) fn new_attempt(&self) {
         self.attempts.fetch_add(1, Ordering::SeqCst);
     }
 
     pub(crate) fn new_success(&self) {
         self.success.fetch_add(1, Ordering::SeqCst);
+        self.consecutive_failures.store(0, Ordering::SeqCst);
         let _ = self
             .connected_at
             .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| {
@@ -195,12 +669,86 @@ impl RelayConnectionStats {
             });
     }
 
+    pub(crate) fn new_rejected_event(&self) {
+        self.rejected_events.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn new_filter_mismatch(&self) {
+        self.filter_mismatches.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn new_received_message(&self, msg: &RelayMessage) {
+        let counter: &AtomicUsize = match msg {
+            RelayMessage::Event { .. } => &self.received_events,
+            RelayMessage::EndOfStoredEvents(..) => &self.received_eose,
+            RelayMessage::Ok { .. } => &self.received_ok,
+            RelayMessage::Notice { .. } => &self.received_notices,
+            RelayMessage::Auth { .. } => &self.received_auth,
+            RelayMessage::Count { .. } | RelayMessage::Closed { .. } => return,
+        };
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn new_unknown_message(&self) {
+        self.received_unknown.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn new_oversized_message(&self) {
+        self.oversized_messages.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn new_dropped_stale_message(&self) {
+        self.dropped_stale_messages.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn new_failure(&self) {
+        self.consecutive_failures.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn new_status(&self, previous: &RelayStatus, status: &RelayStatus) {
+        let connected: bool = status == &RelayStatus::Connected;
+        self.connected.store(connected, Ordering::SeqCst);
+        if previous == &RelayStatus::Connected && status == &RelayStatus::Disconnected {
+            self.disconnected_count.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Update the exponentially-weighted moving average of the latency
+    pub(crate) fn save_latency(&self, latency: Duration) {
+        let sample: u64 = latency.as_millis() as u64;
+        let _ = self
+            .latency
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |avg| {
+                if avg == 0 {
+                    Some(sample.max(1))
+                } else {
+                    // alpha = 0.2
+                    Some(((avg * 4 + sample) / 5).max(1))
+                }
+            });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn new_pong(&self) {
+        self.pong_at
+            .store(Timestamp::now().as_u64(), Ordering::SeqCst);
+    }
+
+    pub(crate) fn save_handshake_duration(&self, duration: Duration) {
+        self.handshake_duration
+            .store(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+
     pub(crate) fn add_bytes_sent(&self, size: usize) {
         self.bytes_sent.fetch_add(size, Ordering::SeqCst);
+        self.sent_at
+            .store(Timestamp::now().as_u64(), Ordering::SeqCst);
     }
 
     pub(crate) fn add_bytes_received(&self, size: usize) {
         self.bytes_received.fetch_add(size, Ordering::SeqCst);
+        self.received_at
+            .store(Timestamp::now().as_u64(), Ordering::SeqCst);
     }
 }
 
@@ -246,6 +794,28 @@ pub struct ActiveSubscription {
     id: SubscriptionId,
     /// Subscriptions filters
     filters: Vec<Filter>,
+    /// Fraction of received events to forward (from `0.0` to `1.0`)
+    sample_rate: Option<f64>,
+    /// Number of sampled events
+    sampled: Arc<AtomicU64>,
+    /// Number of events dropped by sampling
+    dropped: Arc<AtomicU64>,
+    /// Number of events received
+    received: Arc<AtomicU64>,
+    /// Timestamp of the last event received
+    last_event_at: Arc<AtomicU64>,
+    /// Options applied after EOSE (default: none, keep the subscription open)
+    opts: Option<FilterOptions>,
+    /// EOSE received
+    eose: Arc<AtomicBool>,
+    /// Number of events received after EOSE
+    after_eose: Arc<AtomicUsize>,
+    /// Rewrite `since` to the newest event received when resubscribing
+    live_tail: bool,
+    /// `created_at` of the newest event received
+    newest_event: Arc<AtomicU64>,
+    /// Closed by [`Relay::pause_subscription`]
+    paused: bool,
 }
 
 impl Default for ActiveSubscription {
@@ -257,10 +827,7 @@ impl Default for ActiveSubscription {
 impl ActiveSubscription {
     /// Create new empty [`ActiveSubscription`]
     pub fn new() -> Self {
-        Self {
-            id: SubscriptionId::generate(),
-            filters: Vec::new(),
-        }
+        Self::with_filters(Vec::new())
     }
 
     /// Create new empty [`ActiveSubscription`]
@@ -268,6 +835,17 @@ impl ActiveSubscription {
         Self {
             id: SubscriptionId::generate(),
             filters,
+            sample_rate: None,
+            sampled: Arc::new(AtomicU64::new(0)),
+            dropped: Arc::new(AtomicU64::new(0)),
+            received: Arc::new(AtomicU64::new(0)),
+            last_event_at: Arc::new(AtomicU64::new(0)),
+            opts: None,
+            eose: Arc::new(AtomicBool::new(false)),
+            after_eose: Arc::new(AtomicUsize::new(0)),
+            live_tail: false,
+            newest_event: Arc::new(AtomicU64::new(0)),
+            paused: false,
         }
     }
 
@@ -280,6 +858,159 @@ impl ActiveSubscription {
     pub fn filters(&self) -> Vec<Filter> {
         self.filters.clone()
     }
+
+    /// Get [`FilterOptions`]
+    pub fn opts(&self) -> Option<FilterOptions> {
+        self.opts
+    }
+
+    /// Check if the subscription is a live tail, see [`Relay::set_live_tail`]
+    pub fn is_live_tail(&self) -> bool {
+        self.live_tail
+    }
+
+    /// Check if the subscription is paused, see [`Relay::pause_subscription`]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Get sample rate
+    pub fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Number of events forwarded by sampling
+    pub fn sampled(&self) -> u64 {
+        self.sampled.load(Ordering::SeqCst)
+    }
+
+    /// Number of events dropped by sampling
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::SeqCst)
+    }
+
+    /// Number of events received
+    pub fn events_received(&self) -> u64 {
+        self.received.load(Ordering::SeqCst)
+    }
+
+    /// When the last event has been received (`0` if no events received yet)
+    pub fn last_event_at(&self) -> Timestamp {
+        Timestamp::from(self.last_event_at.load(Ordering::SeqCst))
+    }
+
+    fn new_event(&self, created_at: Timestamp) {
+        self.received.fetch_add(1, Ordering::SeqCst);
+        self.last_event_at
+            .store(Timestamp::now().as_u64(), Ordering::SeqCst);
+        self.newest_event
+            .fetch_max(created_at.as_u64(), Ordering::SeqCst);
+    }
+
+    /// Get filters to send when resubscribing
+    ///
+    /// For a live tail, `since` is moved to the newest event received.
+    fn resubscribe_filters(&self) -> Vec<Filter> {
+        let newest: u64 = self.newest_event.load(Ordering::SeqCst);
+        if self.live_tail && newest > 0 {
+            self.filters
+                .iter()
+                .cloned()
+                .map(|mut filter| {
+                    filter.since = Some(Timestamp::from(newest));
+                    filter
+                })
+                .collect()
+        } else {
+            self.filters.clone()
+        }
+    }
+
+    /// Check if a received event must be forwarded, according to the sample rate
+    fn sample(&self) -> bool {
+        match self.sample_rate {
+            Some(sample_rate) => {
+                let forward: bool = rand::random::<f64>() < sample_rate;
+                if forward {
+                    self.sampled.fetch_add(1, Ordering::SeqCst);
+                } else {
+                    self.dropped.fetch_add(1, Ordering::SeqCst);
+                }
+                forward
+            }
+            None => true,
+        }
+    }
+}
+
+/// Additional socket of [`RelayOptions::connections`]
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+struct ExtraSocket {
+    sender: Sender<RelayEvent>,
+    stats: RelayConnectionStats,
+    alive: Arc<AtomicBool>,
+    /// Stop the reader and the writer of the socket
+    close: Arc<watch::Sender<bool>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ExtraSocket {
+    fn close(&self) {
+        self.close.send_replace(true);
+    }
+}
+
+/// Token bucket of [`RelayOptions::rate_limit`]
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: usize) -> Self {
+        Self {
+            tokens: burst as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, rate: f64, burst: usize) {
+        let now = Instant::now();
+        let elapsed: f64 = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst as f64);
+        self.refilled_at = now;
+    }
+}
+
+/// Slot of a running one-shot query, released on drop
+struct QuerySlot {
+    _permit: Option<OwnedSemaphorePermit>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for QuerySlot {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Result of a `get_events_of`, used by [`RelayOptions::query_cache_ttl`]
+#[derive(Debug, Clone)]
+struct CachedQuery {
+    events: Vec<Event>,
+    /// Serialized size of the events, in bytes
+    size: usize,
+    cached_at: Instant,
+    used_at: Instant,
+}
+
+/// Newest event seen for a subscription, used by [`RelayOptions::reject_backwards`]
+#[derive(Debug, Clone, Copy, Default)]
+struct LiveFeed {
+    eose: bool,
+    newest: Option<Timestamp>,
 }
 
 /// Relay
@@ -288,9 +1019,21 @@ pub struct Relay {
     url: Url,
     #[cfg(not(target_arch = "wasm32"))]
     proxy: Option<SocketAddr>,
+    #[cfg(not(target_arch = "wasm32"))]
+    active_proxy: Arc<Mutex<Option<SocketAddr>>>,
     status: Arc<Mutex<RelayStatus>>,
+    /// Read side of the connection closed, while staying connected ([`RelayOptions::disconnect_on_read_close`])
+    read_closed: Arc<AtomicBool>,
     #[cfg(feature = "nip11")]
     document: Arc<Mutex<RelayInformationDocument>>,
+    #[cfg(feature = "nip11")]
+    document_updated_at: Arc<Mutex<Option<Instant>>>,
+    #[cfg(feature = "nip11")]
+    document_refreshing: Arc<AtomicBool>,
+    #[cfg(feature = "nip11")]
+    document_fetched: Arc<AtomicBool>,
+    #[cfg(feature = "nip11")]
+    document_size: Arc<AtomicUsize>,
     opts: RelayOptions,
     stats: RelayConnectionStats,
     scheduled_for_stop: Arc<AtomicBool>,
@@ -298,10 +1041,33 @@ pub struct Relay {
     pool_sender: Sender<RelayPoolMessage>,
     relay_sender: Sender<Message>,
     relay_receiver: Arc<Mutex<Receiver<Message>>>,
+    /// Messages failed to be written, to send again first on the next connection (store-and-forward)
+    replay: Arc<Mutex<VecDeque<Message>>>,
     notification_sender: broadcast::Sender<RelayPoolNotification>,
     subscriptions: Arc<Mutex<HashMap<InternalSubscriptionId, ActiveSubscription>>>,
+    write_checks: Arc<Mutex<HashMap<XOnlyPublicKey, bool>>>,
+    accepted_kinds: Arc<Mutex<HashMap<Kind, bool>>>,
+    pending_eose: Arc<Mutex<HashSet<SubscriptionId>>>,
+    live_feeds: Arc<Mutex<HashMap<SubscriptionId, LiveFeed>>>,
+    auth_challenge: Arc<Mutex<Option<String>>>,
+    drop_oldest: Arc<AtomicUsize>,
+    query_cache: Arc<Mutex<HashMap<u64, CachedQuery>>>,
+    query_semaphore: Option<Arc<Semaphore>>,
+    queries_in_flight: Arc<AtomicUsize>,
+    rate_limiter: Arc<Mutex<TokenBucket>>,
+    ok_senders: Arc<Mutex<OkSenders>>,
+    last_activity_at: Arc<AtomicU64>,
+    idle: Arc<AtomicBool>,
+    wake: Arc<Notify>,
+    #[cfg(not(target_arch = "wasm32"))]
+    extra_sockets: Arc<Mutex<Vec<ExtraSocket>>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    next_socket: Arc<AtomicUsize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pending_ping: Arc<AtomicBool>,
 }
 
+/// Relays are equal if their URLs are equal, in the canonical form of [`normalize_url`]
 impl PartialEq for Relay {
     fn eq(&self, other: &Self) -> bool {
         self.url == other.url
@@ -318,23 +1084,59 @@ impl Relay {
         proxy: Option<SocketAddr>,
         opts: RelayOptions,
     ) -> Self {
-        let (relay_sender, relay_receiver) = mpsc::channel::<Message>(1024);
+        let url: Url = normalize_url(&url);
+        let (relay_sender, relay_receiver) =
+            mpsc::channel::<Message>(opts.get_queue_capacity().max(1));
+        let query_semaphore: Option<Arc<Semaphore>> = opts
+            .get_max_concurrent_queries()
+            .map(|max| Arc::new(Semaphore::new(max.max(1))));
+        let rate_limiter = Arc::new(Mutex::new(TokenBucket::new(
+            opts.get_rate_limit().map(|(_, burst)| burst).unwrap_or(0),
+        )));
 
         Self {
             url,
             proxy,
+            active_proxy: Arc::new(Mutex::new(None)),
             status: Arc::new(Mutex::new(RelayStatus::Initialized)),
             #[cfg(feature = "nip11")]
             document: Arc::new(Mutex::new(RelayInformationDocument::new())),
+            #[cfg(feature = "nip11")]
+            document_updated_at: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "nip11")]
+            document_refreshing: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "nip11")]
+            document_fetched: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "nip11")]
+            document_size: Arc::new(AtomicUsize::new(0)),
             opts,
             stats: RelayConnectionStats::new(),
+            read_closed: Arc::new(AtomicBool::new(false)),
             scheduled_for_stop: Arc::new(AtomicBool::new(false)),
             scheduled_for_termination: Arc::new(AtomicBool::new(false)),
             pool_sender,
             relay_sender,
             relay_receiver: Arc::new(Mutex::new(relay_receiver)),
+            replay: Arc::new(Mutex::new(VecDeque::new())),
             notification_sender,
             subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            write_checks: Arc::new(Mutex::new(HashMap::new())),
+            accepted_kinds: Arc::new(Mutex::new(HashMap::new())),
+            pending_eose: Arc::new(Mutex::new(HashSet::new())),
+            live_feeds: Arc::new(Mutex::new(HashMap::new())),
+            auth_challenge: Arc::new(Mutex::new(None)),
+            drop_oldest: Arc::new(AtomicUsize::new(0)),
+            query_cache: Arc::new(Mutex::new(HashMap::new())),
+            ok_senders: Arc::new(Mutex::new(HashMap::new())),
+            last_activity_at: Arc::new(AtomicU64::new(0)),
+            idle: Arc::new(AtomicBool::new(false)),
+            wake: Arc::new(Notify::new()),
+            query_semaphore,
+            queries_in_flight: Arc::new(AtomicUsize::new(0)),
+            rate_limiter,
+            extra_sockets: Arc::new(Mutex::new(Vec::new())),
+            next_socket: Arc::new(AtomicUsize::new(0)),
+            pending_ping: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -346,26 +1148,60 @@ impl Relay {
         notification_sender: broadcast::Sender<RelayPoolNotification>,
         opts: RelayOptions,
     ) -> Self {
-        let (relay_sender, relay_receiver) = mpsc::channel::<Message>(1024);
+        let url: Url = normalize_url(&url);
+        let (relay_sender, relay_receiver) =
+            mpsc::channel::<Message>(opts.get_queue_capacity().max(1));
+        let query_semaphore: Option<Arc<Semaphore>> = opts
+            .get_max_concurrent_queries()
+            .map(|max| Arc::new(Semaphore::new(max.max(1))));
+        let rate_limiter = Arc::new(Mutex::new(TokenBucket::new(
+            opts.get_rate_limit().map(|(_, burst)| burst).unwrap_or(0),
+        )));
 
         Self {
             url,
             status: Arc::new(Mutex::new(RelayStatus::Initialized)),
             #[cfg(feature = "nip11")]
             document: Arc::new(Mutex::new(RelayInformationDocument::new())),
+            #[cfg(feature = "nip11")]
+            document_updated_at: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "nip11")]
+            document_refreshing: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "nip11")]
+            document_fetched: Arc::new(AtomicBool::new(false)),
+            #[cfg(feature = "nip11")]
+            document_size: Arc::new(AtomicUsize::new(0)),
             opts,
             stats: RelayConnectionStats::new(),
+            read_closed: Arc::new(AtomicBool::new(false)),
             scheduled_for_stop: Arc::new(AtomicBool::new(false)),
             scheduled_for_termination: Arc::new(AtomicBool::new(false)),
             pool_sender,
             relay_sender,
             relay_receiver: Arc::new(Mutex::new(relay_receiver)),
+            replay: Arc::new(Mutex::new(VecDeque::new())),
             notification_sender,
             subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            write_checks: Arc::new(Mutex::new(HashMap::new())),
+            accepted_kinds: Arc::new(Mutex::new(HashMap::new())),
+            pending_eose: Arc::new(Mutex::new(HashSet::new())),
+            live_feeds: Arc::new(Mutex::new(HashMap::new())),
+            auth_challenge: Arc::new(Mutex::new(None)),
+            drop_oldest: Arc::new(AtomicUsize::new(0)),
+            query_cache: Arc::new(Mutex::new(HashMap::new())),
+            ok_senders: Arc::new(Mutex::new(HashMap::new())),
+            last_activity_at: Arc::new(AtomicU64::new(0)),
+            idle: Arc::new(AtomicBool::new(false)),
+            wake: Arc::new(Notify::new()),
+            query_semaphore,
+            queries_in_flight: Arc::new(AtomicUsize::new(0)),
+            rate_limiter,
         }
     }
 
     /// Get relay url
+    ///
+    /// Already in the canonical form used for equality: normalized on construction with [`normalize_url`].
     pub fn url(&self) -> Url {
         self.url.clone()
     }
@@ -376,6 +1212,15 @@ impl Relay {
         self.proxy
     }
 
+    /// Get the proxy used by the current (or last) connection
+    ///
+    /// Can differ from [`Relay::proxy`] when a fallback of [`RelayOptions::proxies`] was used.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn active_proxy(&self) -> Option<SocketAddr> {
+        let active_proxy = self.active_proxy.lock().await;
+        *active_proxy
+    }
+
     /// Get [`RelayStatus`]
     pub async fn status(&self) -> RelayStatus {
         let status = self.status.lock().await;
@@ -388,9 +1233,76 @@ impl Relay {
         RUNTIME.block_on(async { self.status().await })
     }
 
-    async fn set_status(&self, status: RelayStatus) {
+    /// Stream of [`RelayStatus`] changes
+    ///
+    /// The current status is yielded as first item.
+    pub fn status_stream(&self) -> impl Stream<Item = RelayStatus> {
+        let notifications = self.notification_sender.subscribe();
+        futures_util::stream::unfold(
+            (self.clone(), notifications, None::<RelayStatus>),
+            |(relay, mut notifications, last)| async move {
+                let status: RelayStatus = match last {
+                    None => relay.status().await,
+                    Some(last) => loop {
+                        match notifications.recv().await {
+                            Ok(RelayPoolNotification::RelayStatus { url, status, .. })
+                                if url == relay.url && status != last =>
+                            {
+                                break status;
+                            }
+                            Ok(_) => continue,
+                            // Some notifications missed: get the current status
+                            Err(broadcast::error::RecvError::Lagged(_)) => {
+                                let status: RelayStatus = relay.status().await;
+                                if status != last {
+                                    break status;
+                                }
+                            }
+                            Err(broadcast::error::RecvError::Closed) => return None,
+                        }
+                    },
+                };
+                Some((status.clone(), (relay, notifications, Some(status))))
+            },
+        )
+    }
+
+    async fn set_status(&self, status: RelayStatus, reason: Option<DisconnectReason>) {
+        #[cfg(not(target_arch = "wasm32"))]
+        let connected: bool = status == RelayStatus::Connected;
+
         let mut s = self.status.lock().await;
-        *s = status;
+        if *s != status {
+            let previous: RelayStatus = std::mem::replace(&mut *s, status.clone());
+            self.stats.new_status(&previous, &status);
+            let _ = self
+                .notification_sender
+                .send(RelayPoolNotification::RelayStatus {
+                    url: self.url(),
+                    previous,
+                    status,
+                    reason,
+                });
+        }
+        drop(s);
+
+        // The additional sockets live only as long as the main one.
+        // Closed after the status change: `open_extra_sockets` checks the status before registering a socket.
+        #[cfg(not(target_arch = "wasm32"))]
+        if !connected {
+            let mut extra_sockets = self.extra_sockets.lock().await;
+            for socket in extra_sockets.drain(..) {
+                socket.close();
+            }
+        }
+    }
+
+    /// Check if the read side of the connection is closed, while the relay stays connected to publish
+    ///
+    /// Only for write-only relays with [`RelayOptions::disconnect_on_read_close`] disabled: the `OK` messages
+    /// can't be received anymore, so only the [`SendConfirmation::Queued`] and [`SendConfirmation::Written`] sends work.
+    pub fn is_read_closed(&self) -> bool {
+        self.read_closed.load(Ordering::SeqCst)
     }
 
     /// Check if [`Relay`] is connected
@@ -398,23 +1310,163 @@ impl Relay {
         self.status().await == RelayStatus::Connected
     }
 
-    /// Get [`RelayInformationDocument`]
-    #[cfg(feature = "nip11")]
-    pub async fn document(&self) -> RelayInformationDocument {
-        let document = self.document.lock().await;
-        document.clone()
-    }
-
-    /// Get [`RelayInformationDocument`]
-    #[cfg(all(feature = "nip11", feature = "blocking"))]
-    pub fn document_blocking(&self) -> RelayInformationDocument {
-        RUNTIME.block_on(async { self.document().await })
-    }
+    /// Wait until [`Relay`] is connected
+    ///
+    /// Return immediately if already connected, otherwise listen for the status notifications.
+    pub async fn wait_for_connection(&self, timeout: Duration) -> Result<(), Error> {
+        let mut notifications = self.notification_sender.subscribe();
+        if self.is_connected().await {
+            return Ok(());
+        }
+        time::timeout(Some(timeout), async {
+            while let Some(notification) = recv_notification(&mut notifications).await {
+                if let RelayPoolNotification::RelayStatus {
+                    url,
+                    status: RelayStatus::Connected,
+                    ..
+                } = notification
+                {
+                    if self.url == url {
+                        return Ok(());
+                    }
+                }
+            }
+            Err(Error::LoopTerminated)
+        })
+        .await
+        .ok_or(Error::Timeout)?
+    }
+
+    /// Get [`RelayInformationDocument`]
+    ///
+    /// If the cached document is older than [`RelayOptions::document_ttl`],
+    /// a refresh is started in background and the cached one is returned.
+    #[cfg(feature = "nip11")]
+    pub async fn document(&self) -> RelayInformationDocument {
+        if self.is_document_stale().await {
+            self.refresh_document();
+        }
+        let document = self.document.lock().await;
+        document.clone()
+    }
+
+    /// Mark the cached [`RelayInformationDocument`] as stale and fetch it again in background
+    #[cfg(feature = "nip11")]
+    pub async fn invalidate_document(&self) {
+        let mut updated_at = self.document_updated_at.lock().await;
+        *updated_at = None;
+        drop(updated_at);
+        self.refresh_document();
+    }
+
+    /// Check if the relay advertises the support of a NIP in its [`RelayInformationDocument`]
+    ///
+    /// Return `false` if the document hasn't been fetched yet.
+    /// Like [`Relay::document`], the cached document is used and a refresh is started in background if stale.
+    #[cfg(feature = "nip11")]
+    pub async fn supports_nip(&self, nip: u16) -> bool {
+        if self.is_document_stale().await {
+            self.refresh_document();
+        }
+        let document = self.document.lock().await;
+        document
+            .supported_nips
+            .as_ref()
+            .map(|nips| nips.contains(&nip))
+            .unwrap_or(false)
+    }
+
+    /// Fail with [`Error::NotSupported`] if the fetched [`RelayInformationDocument`] lists the supported NIPs without `nip`
+    ///
+    /// An unknown support (document not fetched yet, not served by the relay or without `supported_nips`)
+    /// isn't an error: the request is sent and the reply of the relay settles it.
+    #[cfg(feature = "nip11")]
+    async fn check_nip_support(&self, nip: u16) -> Result<(), Error> {
+        if !self.is_document_fetched() {
+            return Ok(());
+        }
+        if self.is_document_stale().await {
+            self.refresh_document();
+        }
+        let document = self.document.lock().await;
+        match &document.supported_nips {
+            Some(nips) if !nips.contains(&nip) => Err(Error::NotSupported(nip)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check if the [`RelayInformationDocument`] has been fetched at least once
+    ///
+    /// If `false`, [`Relay::document`] returns an empty document.
+    #[cfg(feature = "nip11")]
+    pub fn is_document_fetched(&self) -> bool {
+        self.document_fetched.load(Ordering::SeqCst)
+    }
+
+    /// Get [`RelayInformationDocument`]
+    #[cfg(all(feature = "nip11", feature = "blocking"))]
+    pub fn document_blocking(&self) -> RelayInformationDocument {
+        RUNTIME.block_on(async { self.document().await })
+    }
 
     #[cfg(feature = "nip11")]
     async fn set_document(&self, document: RelayInformationDocument) {
+        let size: usize = nostr::serde_json::to_string(&document)
+            .map(|json| json.len())
+            .unwrap_or_default();
+        self.document_size.store(size, Ordering::SeqCst);
         let mut d = self.document.lock().await;
         *d = document;
+        let mut updated_at = self.document_updated_at.lock().await;
+        *updated_at = Some(Instant::now());
+        self.document_fetched.store(true, Ordering::SeqCst);
+    }
+
+    #[cfg(feature = "nip11")]
+    async fn is_document_stale(&self) -> bool {
+        match self.opts.get_document_ttl() {
+            Some(ttl) => match *self.document_updated_at.lock().await {
+                Some(updated_at) => updated_at.elapsed() >= ttl,
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Request [`RelayInformationDocument`] in background, if not already requesting it
+    #[cfg(feature = "nip11")]
+    fn refresh_document(&self) {
+        if self.document_refreshing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let relay = self.clone();
+        thread::spawn(async move {
+            let timeout: Option<Duration> = relay.opts.get_nip11_timeout();
+            #[cfg(not(target_arch = "wasm32"))]
+            let document = time::timeout(
+                timeout,
+                RelayInformationDocument::get(relay.url(), relay.proxy()),
+            )
+            .await;
+            #[cfg(target_arch = "wasm32")]
+            let document = time::timeout(timeout, RelayInformationDocument::get(relay.url())).await;
+
+            match document {
+                Some(Ok(document)) => relay.set_document(document).await,
+                Some(Err(e)) => tracing::error!(
+                    "Impossible to get information document from {}: {}",
+                    relay.url,
+                    e
+                ),
+                None => tracing::error!(
+                    "Impossible to get information document from {}: timeout",
+                    relay.url
+                ),
+            };
+
+            relay.document_refreshing.store(false, Ordering::SeqCst);
+        });
     }
 
     /// Get [`ActiveSubscription`]
@@ -423,7 +1475,46 @@ impl Relay {
         subscription.clone()
     }
 
+    /// Get the [`InternalSubscriptionId`] and the wire [`SubscriptionId`] of every [`ActiveSubscription`]
+    ///
+    /// Lighter than [`Relay::subscriptions`], since the filters are not cloned.
+    pub async fn subscription_ids(&self) -> Vec<(InternalSubscriptionId, SubscriptionId)> {
+        let subscriptions = self.subscriptions.lock().await;
+        subscriptions
+            .iter()
+            .map(|(internal_id, sub)| (internal_id.clone(), sub.id.clone()))
+            .collect()
+    }
+
+    /// Get number of [`ActiveSubscription`]
+    pub async fn subscription_count(&self) -> usize {
+        let subscriptions = self.subscriptions.lock().await;
+        subscriptions.len()
+    }
+
+    /// Get the [`InternalSubscriptionId`] of the subscription with the given wire [`SubscriptionId`]
+    ///
+    /// Allow to correlate the messages received from the notification channel with the internal subscriptions.
+    pub async fn internal_id_for(&self, sub_id: &SubscriptionId) -> Option<InternalSubscriptionId> {
+        let subscriptions = self.subscriptions.lock().await;
+        subscriptions
+            .iter()
+            .find(|(_, sub)| &sub.id == sub_id)
+            .map(|(internal_id, _)| internal_id.clone())
+    }
+
+    /// Get the wire [`SubscriptionId`] of the subscription with the given [`InternalSubscriptionId`]
+    pub async fn subscription_id_for(
+        &self,
+        internal_id: &InternalSubscriptionId,
+    ) -> Option<SubscriptionId> {
+        let subscriptions = self.subscriptions.lock().await;
+        subscriptions.get(internal_id).map(|sub| sub.id())
+    }
+
     /// Update [`ActiveSubscription`]
+    ///
+    /// The filters are only staged: use [`Relay::commit_subscription`] to send the `REQ`.
     pub async fn update_subscription_filters(
         &self,
         internal_id: InternalSubscriptionId,
@@ -435,158 +1526,1090 @@ impl Relay {
             .or_insert_with(|| ActiveSubscription::with_filters(filters));
     }
 
-    /// Get [`RelayOptions`]
-    pub fn opts(&self) -> RelayOptions {
-        self.opts.clone()
+    /// Set the sample rate of an [`ActiveSubscription`]
+    ///
+    /// Only the given fraction (from `0.0` to `1.0`) of the received events will be forwarded.
+    /// Set to `None` to forward all the events.
+    pub async fn set_sample_rate(
+        &self,
+        internal_id: InternalSubscriptionId,
+        sample_rate: Option<f64>,
+    ) -> Result<(), Error> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        let sub = subscriptions
+            .get_mut(&internal_id)
+            .ok_or(Error::InternalIdNotFound)?;
+        sub.sample_rate = sample_rate.map(|rate| rate.clamp(0.0, 1.0));
+        Ok(())
     }
 
-    /// Get [`RelayConnectionStats`]
-    pub fn stats(&self) -> RelayConnectionStats {
-        self.stats.clone()
+    /// Mark an [`ActiveSubscription`] as live tail
+    ///
+    /// When resubscribing (i.e. after a reconnection), the `since` of its filters is moved
+    /// to the `created_at` of the newest event received, to avoid receiving again the whole backfill.
+    pub async fn set_live_tail(
+        &self,
+        internal_id: InternalSubscriptionId,
+        live_tail: bool,
+    ) -> Result<(), Error> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        let sub = subscriptions
+            .get_mut(&internal_id)
+            .ok_or(Error::InternalIdNotFound)?;
+        sub.live_tail = live_tail;
+        Ok(())
     }
 
-    /// Get queue len
-    pub fn queue(&self) -> usize {
-        self.relay_sender.max_capacity() - self.relay_sender.capacity()
+    /// Get the last `AUTH` challenge received from the relay
+    ///
+    /// Allow to sign the auth event manually, see [`Relay::auth`].
+    pub async fn auth_challenge(&self) -> Option<String> {
+        let challenge = self.auth_challenge.lock().await;
+        challenge.clone()
     }
 
-    fn is_scheduled_for_stop(&self) -> bool {
-        self.scheduled_for_stop.load(Ordering::SeqCst)
-    }
+    /// Save `AUTH` challenge and authenticate, if [`RelayOptions::auto_auth`] is set
+    async fn handle_auth_challenge(&self, challenge: String) {
+        let mut c = self.auth_challenge.lock().await;
+        *c = Some(challenge);
+        drop(c);
 
-    fn schedule_for_stop(&self, value: bool) {
-        let _ = self
-            .scheduled_for_stop
-            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| Some(value));
+        if let Some(keys) = self.opts.get_auth_keys() {
+            let relay = self.clone();
+            // Must not block the message thread: the `OK` is received there
+            thread::spawn(async move {
+                match relay.auth(&keys, Some(Duration::from_secs(10))).await {
+                    Ok(_) => tracing::info!("Authenticated to {}", relay.url),
+                    Err(e) => tracing::error!("Impossible to authenticate to {}: {e}", relay.url),
+                }
+            });
+        }
     }
 
-    fn is_scheduled_for_termination(&self) -> bool {
-        self.scheduled_for_termination.load(Ordering::SeqCst)
+    /// Authenticate to the relay (NIP-42), using the last `AUTH` challenge received
+    ///
+    /// <https://github.com/nostr-protocol/nips/blob/master/42.md>
+    pub async fn auth(&self, keys: &Keys, timeout: Option<Duration>) -> Result<(), Error> {
+        let challenge: String = self
+            .auth_challenge()
+            .await
+            .ok_or(Error::AuthChallengeNotFound)?;
+        let event: Event = EventBuilder::auth(challenge, self.url()).to_event(keys)?;
+        let id: EventId = event.id;
+        time::timeout(timeout, async {
+            let mut notifications = self.notification_sender.subscribe();
+            self.send_msg(ClientMessage::new_auth(event), None).await?;
+            while let Some(notification) = recv_notification(&mut notifications).await {
+                if let RelayPoolNotification::Message(
+                    url,
+                    RelayMessage::Ok {
+                        event_id,
+                        status,
+                        message,
+                    },
+                ) = notification
+                {
+                    if self.url == url && id == event_id {
+                        if status {
+                            return Ok(());
+                        } else {
+                            return Err(Error::AuthFailed(message));
+                        }
+                    }
+                }
+            }
+            Err(Error::LoopTerminated)
+        })
+        .await
+        .ok_or(Error::Timeout)?
     }
 
-    fn schedule_for_termination(&self, value: bool) {
-        let _ =
-            self.scheduled_for_termination
-                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| Some(value));
+    /// Check if the pool or the notification channel is full
+    fn is_lagging(&self) -> bool {
+        if self.pool_sender.capacity() == 0 {
+            return true;
+        }
+        match self.opts.get_notification_capacity() {
+            Some(capacity) => self.notification_sender.len() >= capacity,
+            None => false,
+        }
     }
 
-    /// Connect to relay and keep alive connection
-    pub async fn connect(&self, wait_for_connection: bool) {
-        self.schedule_for_stop(false);
-        self.schedule_for_termination(false);
+    /// Wait until the received messages can be forwarded without lagging,
+    /// if [`RelayOptions::pause_ingestion_on_lag`] is enabled
+    async fn wait_for_ingestion_capacity(&self) {
+        if self.opts.get_pause_ingestion_on_lag() && self.is_lagging() {
+            tracing::warn!("Ingestion paused for {}: consumers are lagging", self.url);
+            while self.is_lagging() && !self.pool_sender.is_closed() {
+                thread::sleep(Duration::from_millis(50)).await;
+            }
+            tracing::debug!("Ingestion resumed for {}", self.url);
+        }
+    }
 
-        if let RelayStatus::Initialized | RelayStatus::Stopped | RelayStatus::Terminated =
-            self.status().await
+    /// Update the counters of the subscription and check if the received event must be forwarded
+    async fn sample_event(&self, subscription_id: &SubscriptionId, created_at: Timestamp) -> bool {
+        let subscriptions = self.subscriptions.lock().await;
+        match subscriptions
+            .values()
+            .find(|sub| &sub.id == subscription_id)
         {
-            if wait_for_connection {
-                self.try_connect().await
-            } else {
-                // Update relay status
-                self.set_status(RelayStatus::Disconnected).await;
+            Some(sub) => {
+                sub.new_event(created_at);
+                sub.sample()
             }
+            None => true,
+        }
+    }
 
-            let relay = self.clone();
-            thread::spawn(async move {
-                loop {
-                    let queue = relay.queue();
-                    if queue > 0 {
-                        tracing::info!("{} messages queued for {}", queue, relay.url());
-                    }
+    /// Check that an event matches the filters of its subscription, if [`RelayOptions::enforce_filter_match`] is enabled
+    ///
+    /// Events of unknown subscriptions (i.e. of `get_events_of`) are accepted,
+    /// since they are checked when handled.
+    async fn check_filter_match(&self, subscription_id: &SubscriptionId, event: &Event) -> bool {
+        if !self.opts.get_enforce_filter_match() {
+            return true;
+        }
+        let subscriptions = self.subscriptions.lock().await;
+        match subscriptions
+            .values()
+            .find(|sub| &sub.id == subscription_id)
+        {
+            Some(sub) => self.match_filters(&sub.filters, subscription_id, event),
+            None => true,
+        }
+    }
 
-                    tracing::debug!(
-                        "{} channel capacity: {}",
-                        relay.url(),
-                        relay.relay_sender.capacity()
-                    );
+    /// Check if the event matches at least one of the filters (always true if `filters` is empty)
+    fn match_filters(
+        &self,
+        filters: &[Filter],
+        subscription_id: &SubscriptionId,
+        event: &Event,
+    ) -> bool {
+        if filters.is_empty() || filters.iter().any(|filter| filter.match_event(event)) {
+            true
+        } else {
+            tracing::warn!(
+                "Dropped event {} from {} not matching the filters of subscription {subscription_id}",
+                event.id,
+                self.url
+            );
+            self.stats.new_filter_mismatch();
+            false
+        }
+    }
 
-                    // Schedule relay for termination
-                    // Needed to terminate the auto reconnect loop, also if the relay is not connected yet.
-                    if relay.is_scheduled_for_stop() {
-                        relay.set_status(RelayStatus::Stopped).await;
-                        relay.schedule_for_stop(false);
-                        tracing::debug!(
-                            "Auto connect loop terminated for {} [stop - schedule]",
-                            relay.url
-                        );
-                        break;
-                    } else if relay.is_scheduled_for_termination() {
-                        relay.set_status(RelayStatus::Terminated).await;
-                        relay.schedule_for_termination(false);
-                        tracing::debug!(
-                            "Auto connect loop terminated for {} [schedule]",
-                            relay.url
-                        );
-                        break;
-                    }
+    /// Check that a live event isn't older than the newest one seen for its subscription,
+    /// by more than [`RelayOptions::reject_backwards`]
+    ///
+    /// Stored events (before `EOSE`) are always accepted.
+    async fn check_backwards(
+        &self,
+        subscription_id: &SubscriptionId,
+        created_at: Timestamp,
+    ) -> bool {
+        if let Some(window) = self.opts.get_reject_backwards() {
+            let mut live_feeds = self.live_feeds.lock().await;
+            let feed = live_feeds.entry(subscription_id.clone()).or_default();
+            if let Some(newest) = feed.newest {
+                if feed.eose && created_at < newest - window {
+                    tracing::warn!(
+                        "Rejected backwards event from {} for subscription {subscription_id}: {created_at} < {newest}",
+                        self.url
+                    );
+                    return false;
+                }
+            }
+            feed.newest = Some(feed.newest.map_or(created_at, |n| n.max(created_at)));
+        }
+        true
+    }
 
-                    // Check status
-                    match relay.status().await {
-                        RelayStatus::Disconnected => relay.try_connect().await,
-                        RelayStatus::Stopped | RelayStatus::Terminated => {
-                            tracing::debug!("Auto connect loop terminated for {}", relay.url);
-                            break;
-                        }
-                        _ => (),
-                    };
+    /// Mark the stored events phase of a subscription as completed
+    async fn live_feed_eose(&self, subscription_id: &SubscriptionId) {
+        if self.opts.get_reject_backwards().is_some() {
+            let mut live_feeds = self.live_feeds.lock().await;
+            live_feeds.entry(subscription_id.clone()).or_default().eose = true;
+        }
+    }
 
-                    thread::sleep(Duration::from_secs(20)).await;
+    /// Restart the tracking of the subscriptions opened (`REQ`) or closed (`CLOSE`) by these messages
+    async fn reset_live_feeds<'a, I>(&self, msgs: I)
+    where
+        I: IntoIterator<Item = &'a ClientMessage>,
+    {
+        if self.opts.get_reject_backwards().is_some() {
+            let mut live_feeds = self.live_feeds.lock().await;
+            for msg in msgs.into_iter() {
+                match msg {
+                    ClientMessage::Req {
+                        subscription_id, ..
+                    }
+                    | ClientMessage::Close(subscription_id) => {
+                        live_feeds.remove(subscription_id);
+                    }
+                    _ => (),
                 }
-            });
+            }
         }
     }
 
-    async fn try_connect(&self) {
-        self.stats.new_attempt();
+    /// Check the filters against the limitations advertised in the [`RelayInformationDocument`]
+    ///
+    /// The filter `limit` is clamped to `max_limit`.
+    #[cfg(feature = "nip11")]
+    async fn apply_limitations(
+        &self,
+        mut filters: Vec<Filter>,
+        new_subscription: bool,
+    ) -> Result<Vec<Filter>, Error> {
+        let limitation = match self.document().await.limitation {
+            Some(limitation) => limitation,
+            None => return Ok(filters),
+        };
 
-        let url: String = self.url.to_string();
+        if let Some(max_filters) = limitation.max_filters {
+            let max_filters = usize::try_from(max_filters).unwrap_or_default();
+            if filters.len() > max_filters {
+                return Err(Error::LimitationExceeded(format!(
+                    "too many filters: {} > {max_filters}",
+                    filters.len()
+                )));
+            }
+        }
 
-        // Set RelayStatus to `Connecting`
-        self.set_status(RelayStatus::Connecting).await;
-        tracing::debug!("Connecting to {}", url);
+        if let Some(max_subscriptions) = limitation.max_subscriptions {
+            let max_subscriptions = usize::try_from(max_subscriptions).unwrap_or_default();
+            let subscriptions: usize = self.subscriptions.lock().await.len();
+            if new_subscription && subscriptions >= max_subscriptions {
+                return Err(Error::TooManySubscriptions(max_subscriptions));
+            }
+        }
 
-        // Request `RelayInformationDocument`
-        #[cfg(feature = "nip11")]
-        {
-            let relay = self.clone();
-            thread::spawn(async move {
-                #[cfg(not(target_arch = "wasm32"))]
-                let document = RelayInformationDocument::get(relay.url(), relay.proxy()).await;
-                #[cfg(target_arch = "wasm32")]
-                let document = RelayInformationDocument::get(relay.url()).await;
-
-                match document {
-                    Ok(document) => relay.set_document(document).await,
-                    Err(e) => tracing::error!(
-                        "Impossible to get information document from {}: {}",
-                        relay.url,
-                        e
-                    ),
-                };
-            });
+        if let Some(max_limit) = limitation.max_limit {
+            let max_limit = usize::try_from(max_limit).unwrap_or_default();
+            for filter in filters.iter_mut() {
+                if let Some(limit) = filter.limit {
+                    if limit > max_limit {
+                        tracing::debug!(
+                            "Filter limit clamped to {max_limit} for {} (was {limit})",
+                            self.url
+                        );
+                        filter.limit = Some(max_limit);
+                    }
+                }
+            }
         }
 
-        #[cfg(not(target_arch = "wasm32"))]
-        let connection = net::native::connect(&self.url, self.proxy, None).await;
-        #[cfg(target_arch = "wasm32")]
-        let connection = net::wasm::connect(&self.url).await;
+        Ok(filters)
+    }
 
-        // Connect
-        match connection {
-            Ok((mut ws_tx, mut ws_rx)) => {
-                self.set_status(RelayStatus::Connected).await;
-                tracing::info!("Connected to {}", url);
+    #[cfg(not(feature = "nip11"))]
+    async fn apply_limitations(
+        &self,
+        filters: Vec<Filter>,
+        _new_subscription: bool,
+    ) -> Result<Vec<Filter>, Error> {
+        Ok(filters)
+    }
 
-                self.stats.new_success();
+    /// Check that the event id has the leading zero bits required by the `min_pow_difficulty` of the relay (NIP-13)
+    #[cfg(feature = "nip11")]
+    async fn check_pow(&self, event: &Event) -> Result<(), Error> {
+        let required: i32 = match self
+            .document()
+            .await
+            .limitation
+            .and_then(|l| l.min_pow_difficulty)
+        {
+            Some(required) => required,
+            None => return Ok(()),
+        };
+        let required: u8 = u8::try_from(required.max(0)).unwrap_or(u8::MAX);
+        let actual: u8 = nip13::get_leading_zero_bits(event.id.inner());
+        if actual < required {
+            return Err(Error::InsufficientPow { required, actual });
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "nip11"))]
+    async fn check_pow(&self, _event: &Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Update [`ActiveSubscription`], checking that the [`SubscriptionId`] of a new subscription
+    /// (custom or generated) isn't already used by another subscription
+    async fn update_subscription_filters_checked(
+        &self,
+        internal_id: InternalSubscriptionId,
+        filters: Vec<Filter>,
+        opts: Option<FilterOptions>,
+        id: Option<SubscriptionId>,
+    ) -> Result<(), Error> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        match subscriptions.get_mut(&internal_id) {
+            Some(sub) => {
+                sub.filters = filters;
+                sub.opts = opts;
+                sub.eose.store(false, Ordering::SeqCst);
+                sub.after_eose.store(0, Ordering::SeqCst);
+                sub.newest_event.store(0, Ordering::SeqCst);
+            }
+            None => {
+                let mut sub = ActiveSubscription::with_filters(filters);
+                if let Some(id) = id {
+                    sub.id = id;
+                }
+                if subscriptions.values().any(|s| s.id == sub.id) {
+                    return Err(Error::SubscriptionIdCollision(sub.id));
+                }
+                sub.opts = opts;
+                subscriptions.insert(internal_id, sub);
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the [`FilterOptions`] of the subscription, if any, when its EOSE is received
+    async fn subscription_eose(&self, subscription_id: &SubscriptionId) {
+        let subscriptions = self.subscriptions.lock().await;
+        let opts: FilterOptions = match subscriptions
+            .values()
+            .find(|sub| &sub.id == subscription_id)
+        {
+            Some(ActiveSubscription {
+                opts: Some(opts),
+                eose,
+                ..
+            }) => {
+                eose.store(true, Ordering::SeqCst);
+                *opts
+            }
+            _ => return,
+        };
+        drop(subscriptions);
+
+        match opts {
+            FilterOptions::ExitOnEOSE
+            | FilterOptions::WaitForEventsAfterEOSE(0)
+            | FilterOptions::WaitAfterEOSE { max_events: 0, .. } => {
+                self.auto_unsubscribe(subscription_id.clone(), None)
+            }
+            FilterOptions::WaitForEventsAfterEOSE(_) => (),
+            FilterOptions::WaitDurationAfterEOSE(duration)
+            | FilterOptions::WaitAfterEOSE {
+                max_duration: duration,
+                ..
+            } => self.auto_unsubscribe(subscription_id.clone(), Some(duration)),
+        }
+    }
+
+    /// Count the events received after EOSE, closing the subscription when the cap of its [`FilterOptions`] is reached
+    async fn count_after_eose(&self, subscription_id: &SubscriptionId) {
+        let subscriptions = self.subscriptions.lock().await;
+        let cap_reached: bool = match subscriptions
+            .values()
+            .find(|sub| &sub.id == subscription_id)
+        {
+            Some(sub) if sub.eose.load(Ordering::SeqCst) => {
+                let max_events: usize = match sub.opts {
+                    Some(FilterOptions::WaitForEventsAfterEOSE(num)) => num as usize,
+                    Some(FilterOptions::WaitAfterEOSE { max_events, .. }) => max_events,
+                    _ => return,
+                };
+                sub.after_eose.fetch_add(1, Ordering::SeqCst) + 1 == max_events
+            }
+            _ => false,
+        };
+        drop(subscriptions);
+
+        if cap_reached {
+            self.auto_unsubscribe(subscription_id.clone(), None);
+        }
+    }
+
+    /// Close the subscription in background, after an optional delay
+    fn auto_unsubscribe(&self, subscription_id: SubscriptionId, delay: Option<Duration>) {
+        let relay = self.clone();
+        thread::spawn(async move {
+            if let Some(delay) = delay {
+                thread::sleep(delay).await;
+            }
+
+            // The subscription may have been closed in the meanwhile
+            if let Some(internal_id) = relay.internal_id_for(&subscription_id).await {
+                tracing::debug!(
+                    "Closing subscription {subscription_id} of {} [filter options]",
+                    relay.url
+                );
+                if let Err(e) = relay.unsubscribe_with_internal_id(internal_id, None).await {
+                    tracing::error!(
+                        "Impossible to close subscription {subscription_id} of {}: {e}",
+                        relay.url
+                    );
+                }
+            }
+        });
+    }
+
+    /// Get [`RelayOptions`]
+    pub fn opts(&self) -> RelayOptions {
+        self.opts.clone()
+    }
+
+    /// Get [`RelayConnectionStats`]
+    pub fn stats(&self) -> RelayConnectionStats {
+        self.stats.clone()
+    }
+
+    /// Get a [`RelaySnapshot`] summarizing the current state of the relay
+    pub async fn snapshot(&self) -> RelaySnapshot {
+        RelaySnapshot {
+            url: self.url(),
+            status: self.status().await,
+            #[cfg(not(target_arch = "wasm32"))]
+            proxy: self.active_proxy().await,
+            attempts: self.stats.attempts(),
+            success: self.stats.success(),
+            bytes_sent: self.stats.bytes_sent(),
+            bytes_received: self.stats.bytes_received(),
+            latency: self.stats.latency(),
+            queue: self.queue(),
+            subscriptions: self.subscription_count().await,
+            #[cfg(feature = "nip11")]
+            supported_nips: self.document.lock().await.supported_nips.clone(),
+        }
+    }
+
+    /// Render the stats in Prometheus exposition format, with the relay url as `url` label
+    ///
+    /// To scrape all the relays use [`RelayPool::metrics_prometheus`](pool::RelayPool::metrics_prometheus).
+    #[cfg(feature = "metrics")]
+    pub async fn metrics_prometheus(&self) -> String {
+        metrics::render(&[self.snapshot().await])
+    }
+
+    /// Get the stats of every socket
+    ///
+    /// The first is the main socket (the same of [`Relay::stats`]), followed by the additional ones of [`RelayOptions::connections`].
+    /// Bytes sent and received of the additional sockets are counted also in the main stats.
+    pub async fn socket_stats(&self) -> Vec<RelayConnectionStats> {
+        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+        let mut stats: Vec<RelayConnectionStats> = vec![self.stats()];
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let extra_sockets = self.extra_sockets.lock().await;
+            stats.extend(extra_sockets.iter().map(|socket| socket.stats.clone()));
+        }
+        stats
+    }
+
+    /// Health score in `0..=1`, weighted by [`RelayOptions::scoring_weights`]
+    ///
+    /// Useful to sort the relays. See [`ScoringWeights`] for the formula.
+    pub fn score(&self) -> f64 {
+        self.stats
+            .score(&self.opts.get_scoring_weights(), Timestamp::now())
+    }
+
+    /// Get queue len
+    pub fn queue(&self) -> usize {
+        self.relay_sender.max_capacity() - self.relay_sender.capacity()
+    }
+
+    /// Wait until the queue of outgoing messages is empty
+    ///
+    /// Useful before [`Relay::terminate`], to avoid losing the last queued messages.
+    pub async fn flush(&self, timeout: Duration) -> Result<(), Error> {
+        time::timeout(Some(timeout), async {
+            while self.queue() > 0 {
+                thread::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .ok_or(Error::Timeout)
+    }
+
+    /// Get queue capacity
+    pub fn queue_capacity(&self) -> usize {
+        self.relay_sender.max_capacity()
+    }
+
+    /// Get the size (in bytes) of a [`ClientMessage`] as it would be sent to this relay
+    ///
+    /// Takes into account the custom serializer set in [`RelayOptions`], if any.
+    pub fn estimated_message_size(&self, msg: &ClientMessage) -> usize {
+        self.opts.serialize_msg(msg).len()
+    }
+
+    /// Get estimated memory usage (in bytes)
+    ///
+    /// Computed on demand from the serialized size of the subscriptions and of the cached relay state
+    /// (the sizes of the cached events and of the [`RelayInformationDocument`] are tracked when stored).
+    pub async fn estimated_memory_usage(&self) -> usize {
+        let mut size: usize = {
+            let subscriptions = self.subscriptions.lock().await;
+            subscriptions
+                .iter()
+                .map(|(internal_id, sub)| {
+                    internal_id.to_string().len()
+                        + sub.id.to_string().len()
+                        + sub.filters.iter().map(|f| f.as_json().len()).sum::<usize>()
+                })
+                .sum()
+        };
+
+        {
+            let query_cache = self.query_cache.lock().await;
+            size += query_cache.values().map(|query| query.size).sum::<usize>();
+        }
+
+        {
+            let live_feeds = self.live_feeds.lock().await;
+            size += live_feeds_size(&live_feeds);
+        }
+
+        #[cfg(feature = "nip11")]
+        {
+            size += self.document_size.load(Ordering::SeqCst);
+        }
+
+        size
+    }
+
+    /// Trim the optional caches until the estimated memory usage is under [`RelayOptions::max_memory`]
+    ///
+    /// The cached queries are evicted starting from the least recently used, then the tracking of
+    /// [`RelayOptions::reject_backwards`] is reset. Subscriptions and queued messages are never dropped.
+    async fn enforce_memory_limit(&self) {
+        let max_memory: usize = match self.opts.get_max_memory() {
+            Some(max_memory) => max_memory,
+            None => return,
+        };
+
+        let mut usage: usize = self.estimated_memory_usage().await;
+        if usage <= max_memory {
+            return;
+        }
+
+        tracing::warn!(
+            "Estimated memory usage of {} exceeds the limit: {usage} > {max_memory} bytes. Trimming caches...",
+            self.url
+        );
+
+        {
+            let mut query_cache = self.query_cache.lock().await;
+            while usage > max_memory {
+                let lru: Option<u64> = query_cache
+                    .iter()
+                    .min_by_key(|(_, query)| query.used_at)
+                    .map(|(key, _)| *key);
+                match lru.and_then(|key| query_cache.remove(&key)) {
+                    Some(query) => usage = usage.saturating_sub(query.size),
+                    None => break,
+                }
+            }
+        }
+
+        if usage > max_memory {
+            let mut live_feeds = self.live_feeds.lock().await;
+            usage = usage.saturating_sub(live_feeds_size(&live_feeds));
+            live_feeds.clear();
+        }
+
+        if usage > max_memory {
+            tracing::warn!(
+                "Estimated memory usage of {} still exceeds the limit after trimming: {usage} > {max_memory} bytes",
+                self.url
+            );
+        }
+    }
+
+    /// Check if [`Relay::stop`] has been called and the auto connect loop is winding down
+    ///
+    /// Can be `true` before the status reaches [`RelayStatus::Stopped`].
+    pub fn is_stopping(&self) -> bool {
+        self.is_scheduled_for_stop()
+    }
+
+    /// Check if [`Relay::terminate`] has been called and the auto connect loop is winding down
+    ///
+    /// Can be `true` before the status reaches [`RelayStatus::Terminated`].
+    pub fn is_terminating(&self) -> bool {
+        self.is_scheduled_for_termination()
+    }
+
+    fn is_scheduled_for_stop(&self) -> bool {
+        self.scheduled_for_stop.load(Ordering::SeqCst)
+    }
+
+    fn schedule_for_stop(&self, value: bool) {
+        let _ = self
+            .scheduled_for_stop
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| Some(value));
+    }
+
+    fn is_scheduled_for_termination(&self) -> bool {
+        self.scheduled_for_termination.load(Ordering::SeqCst)
+    }
+
+    fn schedule_for_termination(&self, value: bool) {
+        let _ =
+            self.scheduled_for_termination
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| Some(value));
+    }
+
+    /// Connect to relay and keep alive connection
+    pub async fn connect(&self, wait_for_connection: bool) {
+        self.schedule_for_stop(false);
+        self.schedule_for_termination(false);
+        self.idle.store(false, Ordering::SeqCst);
+
+        if let RelayStatus::Initialized | RelayStatus::Stopped | RelayStatus::Terminated =
+            self.status().await
+        {
+            if wait_for_connection {
+                self.try_connect().await
+            } else {
+                // Update relay status
+                self.set_status(RelayStatus::Disconnected, None).await;
+            }
+
+            let relay = self.clone();
+            thread::spawn(async move {
+                let started = Instant::now();
+                loop {
+                    let queue = relay.queue();
+                    if queue > 0 {
+                        tracing::info!("{} messages queued for {}", queue, relay.url());
+                    }
+
+                    tracing::debug!(
+                        "{} channel capacity: {}",
+                        relay.url(),
+                        relay.relay_sender.capacity()
+                    );
+
+                    // Schedule relay for termination
+                    // Needed to terminate the auto reconnect loop, also if the relay is not connected yet.
+                    if relay.is_scheduled_for_stop() {
+                        relay
+                            .set_status(RelayStatus::Stopped, Some(DisconnectReason::UserRequested))
+                            .await;
+                        relay.schedule_for_stop(false);
+                        tracing::debug!(
+                            "Auto connect loop terminated for {} [stop - schedule]",
+                            relay.url
+                        );
+                        break;
+                    } else if relay.is_scheduled_for_termination() {
+                        relay
+                            .set_status(
+                                RelayStatus::Terminated,
+                                Some(DisconnectReason::UserRequested),
+                            )
+                            .await;
+                        relay.schedule_for_termination(false);
+                        tracing::debug!(
+                            "Auto connect loop terminated for {} [schedule]",
+                            relay.url
+                        );
+                        break;
+                    }
+
+                    // Relay pool dropped: nothing to forward received messages to
+                    if relay.pool_sender.is_closed() {
+                        if let Err(e) = relay.disconnect(DisconnectReason::PoolDropped).await {
+                            tracing::error!("Impossible to disconnect {}: {e}", relay.url);
+                        }
+                        if !relay.is_connected().await {
+                            relay
+                                .set_status(
+                                    RelayStatus::Terminated,
+                                    Some(DisconnectReason::PoolDropped),
+                                )
+                                .await;
+                        }
+                        tracing::debug!(
+                            "Auto connect loop terminated for {} [pool dropped]",
+                            relay.url
+                        );
+                        break;
+                    }
+
+                    // Check status
+                    match relay.status().await {
+                        RelayStatus::Disconnected if relay.is_idle() => (),
+                        RelayStatus::Disconnected => relay.try_connect().await,
+                        RelayStatus::Stopped | RelayStatus::Terminated => {
+                            tracing::debug!("Auto connect loop terminated for {}", relay.url);
+                            break;
+                        }
+                        _ => (),
+                    };
+
+                    if relay.is_idle_timeout_expired().await {
+                        tracing::info!("Connection with {} is idle: disconnecting", relay.url);
+                        relay.idle.store(true, Ordering::SeqCst);
+                        if let Err(e) = relay.disconnect(DisconnectReason::Idle).await {
+                            tracing::error!("Impossible to disconnect {}: {e}", relay.url);
+                        }
+                    }
+
+                    if relay.is_stale().await {
+                        tracing::warn!("Connection with {} is stale", relay.url);
+                        if relay.opts.get_reconnect_on_stale() {
+                            if let Err(e) = relay.disconnect(DisconnectReason::Stale).await {
+                                tracing::error!("Impossible to disconnect {}: {e}", relay.url);
+                            }
+                        }
+                    }
+
+                    let mut interval = if relay.is_connected().await {
+                        relay.opts.get_connected_retry_interval()
+                    } else {
+                        relay
+                            .opts
+                            .get_retry_interval(relay.stats.consecutive_failures())
+                    };
+
+                    // Check auto connect loop lifetime
+                    if let Some(lifetime) = relay.opts.get_loop_max_lifetime() {
+                        let elapsed: Duration = started.elapsed();
+                        if elapsed >= lifetime {
+                            let connected: bool = relay.is_connected().await;
+                            if let Err(e) = relay.terminate().await {
+                                tracing::error!("Impossible to terminate {}: {e}", relay.url);
+                            }
+                            if !connected {
+                                relay
+                                    .set_status(
+                                        RelayStatus::Terminated,
+                                        Some(DisconnectReason::UserRequested),
+                                    )
+                                    .await;
+                                relay.schedule_for_termination(false);
+                            }
+                            tracing::debug!(
+                                "Auto connect loop terminated for {} [lifetime]",
+                                relay.url
+                            );
+                            break;
+                        }
+                        interval = interval.min(lifetime - elapsed);
+                    }
+
+                    // Woken up early by the next message sent when idle
+                    tokio::select! {
+                        _ = thread::sleep(interval) => (),
+                        _ = relay.wake.notified() => (),
+                    }
+                }
+            });
+        }
+    }
+
+    /// Refuse plaintext `ws://` urls, unless [`RelayOptions::allow_insecure`] is set
+    ///
+    /// Tor onion services are already end-to-end encrypted, so are always allowed.
+    fn check_secure(&self) -> Result<(), Error> {
+        let onion: bool = self
+            .url
+            .host_str()
+            .map(|host| host.ends_with(".onion"))
+            .unwrap_or(false);
+        if self.url.scheme() == "ws" && !onion && !self.opts.get_allow_insecure() {
+            return Err(Error::InsecureConnection(self.url.clone()));
+        }
+        Ok(())
+    }
+
+    /// Open a socket to the relay, with the custom [`Transport`] or trying the proxies in order until one succeeds
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn open_socket(&self) -> Result<(WsSink, WsStream), String> {
+        if let Some(transport) = self.opts.get_transport() {
+            return match time::timeout(
+                self.opts.get_connection_timeout(),
+                transport.connect(&self.url),
+            )
+            .await
+            {
+                Some(res) => res.map_err(|e| e.to_string()),
+                None => Err(String::from("connection timeout")),
+            };
+        }
+
+        let mut proxies: Vec<Option<SocketAddr>> = self
+            .proxy
+            .into_iter()
+            .chain(self.opts.get_proxies().iter().copied())
+            .map(Some)
+            .collect();
+        if proxies.is_empty() {
+            proxies.push(None);
+        }
+
+        let mut last_error = String::new();
+        for proxy in proxies.into_iter() {
+            let fut = net::native::connect(
+                &self.url,
+                proxy,
+                self.opts.get_connection_config(),
+                self.opts.get_ip_version(),
+                self.opts.get_resolver(),
+            );
+            match time::timeout(self.opts.get_connection_timeout(), fut).await {
+                Some(Ok((ws_tx, ws_rx))) => {
+                    let mut active_proxy = self.active_proxy.lock().await;
+                    *active_proxy = proxy;
+                    return Ok(transport::boxed(ws_tx, ws_rx));
+                }
+                Some(Err(e)) => last_error = e.to_string(),
+                None => last_error = String::from("connection timeout"),
+            };
+            if let Some(proxy) = proxy {
+                tracing::warn!(
+                    "Impossible to connect to {} via {proxy}: {last_error}",
+                    self.url
+                );
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Open the additional sockets of [`RelayOptions::connections`]
+    ///
+    /// They are used only to publish events: the subscriptions stay on the main socket.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn open_extra_sockets(&self) {
+        let session: usize = self.stats.success();
+        for index in 1..self.opts.get_connections() {
+            let stats = RelayConnectionStats::new();
+            stats.new_attempt();
+            let now = Instant::now();
+            let (mut ws_tx, mut ws_rx) = match self.open_socket().await {
+                Ok(socket) => socket,
+                Err(e) => {
+                    stats.new_failure();
+                    tracing::warn!("Impossible to open socket #{index} to {}: {e}", self.url);
+                    continue;
+                }
+            };
+            stats.new_success();
+            stats.save_handshake_duration(now.elapsed());
+            tracing::debug!("Opened socket #{index} to {}", self.url);
+
+            let (sender, mut receiver) =
+                mpsc::channel::<RelayEvent>(self.opts.get_queue_capacity().max(1));
+            let alive = Arc::new(AtomicBool::new(true));
+            let (close, closed) = watch::channel(false);
+
+            // Disconnected or reconnected in the meantime: drop the socket.
+            // Checked while holding the lock, so a disconnection can't happen before the socket is registered.
+            {
+                let mut extra_sockets = self.extra_sockets.lock().await;
+                if !self.is_connected().await || self.stats.success() != session {
+                    drop(extra_sockets);
+                    let _ = ws_tx.close().await;
+                    break;
+                }
+                extra_sockets.push(ExtraSocket {
+                    sender,
+                    stats: stats.clone(),
+                    alive: alive.clone(),
+                    close: Arc::new(close),
+                });
+            }
+
+            // Writer
+            let relay = self.clone();
+            let socket_stats = stats.clone();
+            let socket_alive = alive.clone();
+            let mut writer_closed = closed.clone();
+            thread::spawn(async move {
+                'outer: while let Some(relay_event) = tokio::select! {
+                    relay_event = receiver.recv() => relay_event,
+                    _ = writer_closed.changed() => None,
+                } {
+                    let msgs: Vec<ClientMessage> = match relay_event {
+                        RelayEvent::SendMsg(msg) => vec![*msg],
+                        RelayEvent::Batch(batch) => batch,
+                        _ => continue,
+                    };
+                    for msg in msgs.iter() {
+                        let json = relay.opts.serialize_msg(msg);
+                        let size: usize = json.len();
+                        if let Err(e) = ws_tx.send(WsMessage::Text(json)).await {
+                            tracing::error!(
+                                "Impossible to send msg to {} (socket #{index}): {e}",
+                                relay.url
+                            );
+                            break 'outer;
+                        }
+                        relay.stats.add_bytes_sent(size);
+                        socket_stats.add_bytes_sent(size);
+                    }
+                }
+                socket_alive.store(false, Ordering::SeqCst);
+                let _ = ws_tx.close().await;
+                tracing::debug!("Exited from writer of socket #{index} of {}", relay.url);
+            });
+
+            // Reader: merged with the messages of the main socket
+            let relay = self.clone();
+            let socket_stats = stats.clone();
+            let socket_alive = alive;
+            let mut reader_closed = closed;
+            thread::spawn(async move {
+                while let Some(msg_res) = tokio::select! {
+                    msg_res = ws_rx.next() => msg_res,
+                    _ = reader_closed.changed() => None,
+                } {
+                    if let Ok(msg) = msg_res {
+                        if let WsMessage::Close(_) | WsMessage::Pong(_) = msg {
+                            continue;
+                        }
+                        let data: Vec<u8> = msg.into_data();
+                        socket_stats.add_bytes_received(data.len());
+                        if relay.handle_message(data).await {
+                            break;
+                        }
+                    }
+                }
+                socket_alive.store(false, Ordering::SeqCst);
+                tracing::debug!("Exited from reader of socket #{index} of {}", relay.url);
+            });
+        }
+    }
+
+    /// Send the [`RelayEvent`] to one of the sockets of [`RelayOptions::connections`] (round-robin)
+    ///
+    /// Only `EVENT` messages are load-balanced. Return the [`RelayEvent`] back if it must be sent to the main socket.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn send_to_extra_socket(&self, relay_event: RelayEvent) -> Option<RelayEvent> {
+        let only_events: bool = match &relay_event {
+            RelayEvent::SendMsg(msg) => matches!(**msg, ClientMessage::Event(..)),
+            RelayEvent::Batch(batch) => batch
+                .iter()
+                .all(|msg| matches!(msg, ClientMessage::Event(..))),
+            _ => false,
+        };
+        if !only_events {
+            return Some(relay_event);
+        }
+
+        let sender: Sender<RelayEvent> = {
+            let extra_sockets = self.extra_sockets.lock().await;
+            let alive: Vec<&ExtraSocket> = extra_sockets
+                .iter()
+                .filter(|socket| socket.alive.load(Ordering::SeqCst))
+                .collect();
+            if alive.is_empty() {
+                return Some(relay_event);
+            }
+            // Index 0 is the main socket
+            let index: usize = self.next_socket.fetch_add(1, Ordering::SeqCst) % (alive.len() + 1);
+            match index.checked_sub(1) {
+                Some(index) => alive[index].sender.clone(),
+                None => return Some(relay_event),
+            }
+        };
+
+        match sender.send(relay_event).await {
+            Ok(()) => None,
+            Err(e) => Some(e.0),
+        }
+    }
+
+    async fn try_connect(&self) {
+        self.stats.new_attempt();
+
+        if let Err(e) = self.check_secure() {
+            self.stats.new_failure();
+            self.set_status(
+                RelayStatus::Disconnected,
+                Some(DisconnectReason::ConnectionLost),
+            )
+            .await;
+            tracing::error!("Impossible to connect to {}: {e}", self.url);
+            return;
+        }
+
+        let url: String = self.url.to_string();
+
+        // Set RelayStatus to `Connecting`
+        self.set_status(RelayStatus::Connecting, None).await;
+        tracing::debug!("Connecting to {}", url);
+
+        // Request `RelayInformationDocument`
+        #[cfg(feature = "nip11")]
+        self.refresh_document();
+
+        let now = Instant::now();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let connection = self.open_socket().await;
+        #[cfg(target_arch = "wasm32")]
+        let connection = match time::timeout(
+            self.opts.get_connection_timeout(),
+            net::wasm::connect(&self.url),
+        )
+        .await
+        {
+            Some(res) => res.map_err(|e| e.to_string()),
+            None => Err(String::from("connection timeout")),
+        };
+
+        // Connect
+        match connection {
+            Ok((mut ws_tx, mut ws_rx)) => {
+                self.set_status(RelayStatus::Connected, None).await;
+                tracing::info!("Connected to {}", url);
+
+                self.stats.new_success();
+                self.stats.save_handshake_duration(now.elapsed());
+
+                // The `AUTH` challenge is valid only for the connection it was sent on
+                let mut challenge = self.auth_challenge.lock().await;
+                *challenge = None;
+                drop(challenge);
+
+                let relay = self.clone();
+                thread::spawn(async move {
+                    tracing::debug!("Relay Event Thread Started");
+                    let mut rx = relay.relay_receiver.lock().await;
+                    loop {
+                        let replayed: Option<Message> = relay.replay.lock().await.pop_front();
+                        let (relay_event, oneshot_sender, queued_at) = match replayed {
+                            Some(msg) => msg,
+                            None => match rx.recv().await {
+                                Some(msg) => msg,
+                                None => break,
+                            },
+                        };
+
+                        if relay.must_drop_oldest(&relay_event) {
+                            if let Some(sender) = oneshot_sender {
+                                let _ = sender.send(false);
+                            }
+                            continue;
+                        }
+
+                        if relay.is_expired(&relay_event, queued_at) {
+                            tracing::warn!("Dropped stale queued message for {}", relay.url);
+                            relay.stats.new_dropped_stale_message();
+                            if let Some(sender) = oneshot_sender {
+                                let _ = sender.send(false);
+                            }
+                            continue;
+                        }
 
-                let relay = self.clone();
-                thread::spawn(async move {
-                    tracing::debug!("Relay Event Thread Started");
-                    let mut rx = relay.relay_receiver.lock().await;
-                    while let Some((relay_event, oneshot_sender)) = rx.recv().await {
                         match relay_event {
                             RelayEvent::SendMsg(msg) => {
-                                let json = msg.as_json();
-                                let size: usize = json.as_bytes().len();
+                                let json = relay.opts.serialize_msg(&msg);
+                                let size: usize = json.len();
                                 tracing::debug!(
                                     "Sending {json} to {} (size: {size} bytes)",
                                     relay.url
@@ -609,7 +2632,13 @@ impl Relay {
                                             relay.url(),
                                             e.to_string()
                                         );
-                                        if let Some(sender) = oneshot_sender {
+                                        if relay.opts.get_store_and_forward() {
+                                            relay.replay.lock().await.push_back((
+                                                RelayEvent::SendMsg(msg),
+                                                oneshot_sender,
+                                                queued_at,
+                                            ));
+                                        } else if let Some(sender) = oneshot_sender {
                                             if let Err(e) = sender.send(false) {
                                                 tracing::error!(
                                                     "Impossible to send oneshot msg: {}",
@@ -617,21 +2646,28 @@ impl Relay {
                                                 );
                                             }
                                         }
+                                        relay
+                                            .set_status(
+                                                RelayStatus::Disconnected,
+                                                Some(DisconnectReason::WriteError),
+                                            )
+                                            .await;
                                         break;
                                     }
                                 }
                             }
-                            RelayEvent::Batch(msgs) => {
-                                let len = msgs.len();
-                                let size: usize =
-                                    msgs.iter().map(|msg| msg.as_json().as_bytes().len()).sum();
+                            RelayEvent::Batch(batch) => {
+                                let len = batch.len();
+                                let msgs: Vec<String> = batch
+                                    .iter()
+                                    .map(|msg| relay.opts.serialize_msg(msg))
+                                    .collect();
+                                let size: usize = msgs.iter().map(|msg| msg.len()).sum();
                                 tracing::debug!(
                                     "Sending {len} messages to {} (size: {size} bytes)",
                                     relay.url
                                 );
-                                let msgs = msgs
-                                    .into_iter()
-                                    .map(|msg| Ok(WsMessage::Text(msg.as_json())));
+                                let msgs = msgs.into_iter().map(WsMessage::Text).map(Ok);
                                 let mut stream = futures_util::stream::iter(msgs);
                                 match ws_tx.send_all(&mut stream).await {
                                     Ok(_) => {
@@ -651,7 +2687,13 @@ impl Relay {
                                             relay.url(),
                                             e.to_string()
                                         );
-                                        if let Some(sender) = oneshot_sender {
+                                        if relay.opts.get_store_and_forward() {
+                                            relay.replay.lock().await.push_back((
+                                                RelayEvent::Batch(batch),
+                                                oneshot_sender,
+                                                queued_at,
+                                            ));
+                                        } else if let Some(sender) = oneshot_sender {
                                             if let Err(e) = sender.send(false) {
                                                 tracing::error!(
                                                     "Impossible to send oneshot msg: {}",
@@ -659,20 +2701,50 @@ impl Relay {
                                                 );
                                             }
                                         }
+                                        relay
+                                            .set_status(
+                                                RelayStatus::Disconnected,
+                                                Some(DisconnectReason::WriteError),
+                                            )
+                                            .await;
                                         break;
                                     }
                                 }
                             }
-                            RelayEvent::Close => {
+                            RelayEvent::Ping => {
+                                #[cfg(not(target_arch = "wasm32"))]
+                                if let Err(e) = ws_tx.send(WsMessage::Ping(Vec::new())).await {
+                                    tracing::error!("Impossible to ping {}: {e}", relay.url);
+                                    relay
+                                        .set_status(
+                                            RelayStatus::Disconnected,
+                                            Some(DisconnectReason::WriteError),
+                                        )
+                                        .await;
+                                    break;
+                                }
+                            }
+                            RelayEvent::Close(reason) => {
                                 let _ = ws_tx.close().await;
-                                relay.set_status(RelayStatus::Disconnected).await;
-                                tracing::info!("Disconnected from {}", url);
+                                // Relay orphaned: no reason to reconnect
+                                let status = if reason == DisconnectReason::PoolDropped {
+                                    RelayStatus::Terminated
+                                } else {
+                                    RelayStatus::Disconnected
+                                };
+                                tracing::info!("Disconnected from {}: {reason}", url);
+                                relay.set_status(status, Some(reason)).await;
                                 break;
                             }
                             RelayEvent::Stop => {
                                 if relay.is_scheduled_for_stop() {
                                     let _ = ws_tx.close().await;
-                                    relay.set_status(RelayStatus::Stopped).await;
+                                    relay
+                                        .set_status(
+                                            RelayStatus::Stopped,
+                                            Some(DisconnectReason::UserRequested),
+                                        )
+                                        .await;
                                     relay.schedule_for_stop(false);
                                     tracing::info!("Stopped {}", url);
                                     break;
@@ -681,7 +2753,12 @@ impl Relay {
                             RelayEvent::Terminate => {
                                 if relay.is_scheduled_for_termination() {
                                     let _ = ws_tx.close().await;
-                                    relay.set_status(RelayStatus::Terminated).await;
+                                    relay
+                                        .set_status(
+                                            RelayStatus::Terminated,
+                                            Some(DisconnectReason::UserRequested),
+                                        )
+                                        .await;
                                     relay.schedule_for_termination(false);
                                     tracing::info!("Completely disconnected from {}", url);
                                     break;
@@ -692,73 +2769,120 @@ impl Relay {
                     tracing::debug!("Exited from Relay Event Thread");
                 });
 
-                let relay = self.clone();
-                thread::spawn(async move {
-                    tracing::debug!("Relay Message Thread Started");
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(interval) = self.opts.get_ping_interval() {
+                    self.pending_ping.store(false, Ordering::SeqCst);
+                    let relay = self.clone();
+                    let session: usize = self.stats.success();
+                    thread::spawn(async move {
+                        tracing::debug!("Relay Keepalive Thread Started");
+                        loop {
+                            thread::sleep(interval).await;
+
+                            // Exit if disconnected or reconnected in the meantime
+                            if !relay.is_connected().await || relay.stats.success() != session {
+                                break;
+                            }
 
-                    async fn func(relay: &Relay, data: Vec<u8>) -> bool {
-                        relay.stats.add_bytes_received(data.len());
-                        match String::from_utf8(data) {
-                            Ok(data) => match RelayMessage::from_json(&data) {
-                                Ok(msg) => {
-                                    tracing::trace!("Received message to {}: {:?}", relay.url, msg);
-                                    if let Err(err) = relay
-                                        .pool_sender
-                                        .send(RelayPoolMessage::ReceivedMsg {
-                                            relay_url: relay.url(),
-                                            msg,
-                                        })
-                                        .await
-                                    {
-                                        tracing::error!(
-                                            "Impossible to send ReceivedMsg to pool: {}",
-                                            &err
-                                        );
-                                        return true; // Exit
-                                    };
-                                }
-                                Err(e) => {
-                                    match e {
-                                        MessageHandleError::EmptyMsg => (),
-                                        _ => tracing::error!("{e}: {data}"),
-                                    };
+                            if relay.pending_ping.load(Ordering::SeqCst) {
+                                tracing::warn!(
+                                    "No pong received from {} in {interval:?}",
+                                    relay.url
+                                );
+                                if let Err(e) =
+                                    relay.disconnect(DisconnectReason::PingTimeout).await
+                                {
+                                    tracing::error!("Impossible to disconnect {}: {e}", relay.url);
                                 }
-                            },
-                            Err(err) => tracing::error!("{}", err),
+                                break;
+                            }
+
+                            relay.pending_ping.store(true, Ordering::SeqCst);
+                            if let Err(e) = relay.send_relay_event(RelayEvent::Ping, None).await {
+                                tracing::error!("Impossible to ping {}: {e}", relay.url);
+                            }
                         }
+                        tracing::debug!("Exited from Keepalive Thread of {}", relay.url);
+                    });
+                }
 
-                        false
-                    }
+                self.read_closed.store(false, Ordering::SeqCst);
+
+                let relay = self.clone();
+                thread::spawn(async move {
+                    tracing::debug!("Relay Message Thread Started");
+
+                    let mut reason = DisconnectReason::ConnectionLost;
 
                     #[cfg(not(target_arch = "wasm32"))]
-                    while let Some(msg_res) = ws_rx.next().await {
+                    while let Some(msg_res) = {
+                        relay.wait_for_ingestion_capacity().await;
+                        ws_rx.next().await
+                    } {
                         if let Ok(msg) = msg_res {
+                            if let WsMessage::Close(frame) = &msg {
+                                if let Some(frame) = frame {
+                                    tracing::info!(
+                                        "Received close frame from {}: code={}, reason={}",
+                                        relay.url,
+                                        frame.code,
+                                        frame.reason
+                                    );
+                                    reason = DisconnectReason::CloseFrame {
+                                        code: frame.code.into(),
+                                        reason: frame.reason.to_string(),
+                                    };
+                                }
+                                continue;
+                            }
+                            if let WsMessage::Pong(_) = msg {
+                                relay.pending_ping.store(false, Ordering::SeqCst);
+                                relay.stats.new_pong();
+                                continue;
+                            }
                             let data: Vec<u8> = msg.into_data();
-                            let exit: bool = func(&relay, data).await;
+                            let exit: bool = relay.handle_message(data).await;
                             if exit {
+                                reason = DisconnectReason::PoolDropped;
                                 break;
                             }
                         }
                     }
 
                     #[cfg(target_arch = "wasm32")]
-                    while let Some(msg) = ws_rx.next().await {
+                    while let Some(msg) = {
+                        relay.wait_for_ingestion_capacity().await;
+                        ws_rx.next().await
+                    } {
                         let data: Vec<u8> = msg.as_ref().to_vec();
-                        let exit: bool = func(&relay, data).await;
+                        let exit: bool = relay.handle_message(data).await;
                         if exit {
+                            reason = DisconnectReason::PoolDropped;
                             break;
                         }
                     }
 
                     tracing::debug!("Exited from Message Thread of {}", relay.url);
 
-                    if let Err(err) = relay.disconnect().await {
+                    // A write-only relay can keep publishing without the read side
+                    if reason != DisconnectReason::PoolDropped
+                        && !relay.opts.get_disconnect_on_read_close()
+                    {
+                        tracing::warn!(
+                            "Read side of {} closed: keeping the connection to publish",
+                            relay.url
+                        );
+                        relay.read_closed.store(true, Ordering::SeqCst);
+                        return;
+                    }
+
+                    if let Err(err) = relay.disconnect(reason).await {
                         tracing::error!("Impossible to disconnect {}: {}", relay.url, err);
                     }
                 });
 
                 // Subscribe to relay
-                if self.opts.read() {
+                if self.opts.read() && self.opts.get_auto_resubscribe() {
                     if let Err(e) = self.resubscribe_all(None).await {
                         tracing::error!(
                             "Impossible to subscribe to {}: {}",
@@ -767,32 +2891,332 @@ impl Relay {
                         )
                     }
                 }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                self.open_extra_sockets().await;
             }
             Err(err) => {
-                self.set_status(RelayStatus::Disconnected).await;
+                self.stats.new_failure();
+                self.set_status(
+                    RelayStatus::Disconnected,
+                    Some(DisconnectReason::ConnectionLost),
+                )
+                .await;
                 tracing::error!("Impossible to connect to {}: {}", url, err);
             }
         };
     }
 
-    fn send_relay_event(
+    /// Handle a message received from the relay: return `true` if the message thread must exit
+    async fn handle_message(&self, data: Vec<u8>) -> bool {
+        self.stats.add_bytes_received(data.len());
+        if let Some(max_message_size) = self.opts.get_max_message_size() {
+            if data.len() > max_message_size {
+                tracing::warn!(
+                    "Dropped message from {}: too big ({} > {max_message_size} bytes)",
+                    self.url,
+                    data.len()
+                );
+                self.stats.new_oversized_message();
+                return false;
+            }
+        }
+        match String::from_utf8(data) {
+            Ok(data) => {
+                self.opts.raw_message(&data);
+                match RelayMessage::from_json(&data) {
+                    Ok(msg) => {
+                        tracing::trace!("Received message to {}: {:?}", self.url, msg);
+                        self.stats.new_received_message(&msg);
+                        if let RelayMessage::Event {
+                            subscription_id,
+                            event,
+                        } = &msg
+                        {
+                            if self.opts.get_verify_events() && event.verify().is_err() {
+                                tracing::warn!(
+                                    "Rejected invalid event {} from {}",
+                                    event.id,
+                                    self.url
+                                );
+                                self.stats.new_rejected_event();
+                                return false;
+                            }
+                            if !self.check_filter_match(subscription_id, event).await {
+                                return false;
+                            }
+                            if !self
+                                .check_backwards(subscription_id, event.created_at)
+                                .await
+                            {
+                                return false;
+                            }
+                            if !self.sample_event(subscription_id, event.created_at).await {
+                                return false;
+                            }
+                            self.count_after_eose(subscription_id).await;
+                        }
+                        if let RelayMessage::Ok {
+                            event_id,
+                            status,
+                            message,
+                        } = &msg
+                        {
+                            self.fulfill_ok(event_id, *status, message).await;
+                        }
+                        if let RelayMessage::Auth { challenge } = &msg {
+                            self.handle_auth_challenge(challenge.clone()).await;
+                        }
+                        if let RelayMessage::Closed {
+                            subscription_id,
+                            message,
+                        } = &msg
+                        {
+                            tracing::warn!(
+                                "Subscription {subscription_id} closed by {}: {message}",
+                                self.url
+                            );
+                            // No EOSE will be received
+                            self.remove_pending_eose(subscription_id).await;
+                        }
+                        if let RelayMessage::EndOfStoredEvents(subscription_id) = &msg {
+                            self.live_feed_eose(subscription_id).await;
+                            self.remove_pending_eose(subscription_id).await;
+                            self.subscription_eose(subscription_id).await;
+                        }
+                        if let Err(err) = self
+                            .pool_sender
+                            .send(RelayPoolMessage::ReceivedMsg {
+                                relay_url: self.url(),
+                                msg,
+                            })
+                            .await
+                        {
+                            tracing::error!("Impossible to send ReceivedMsg to pool: {}", &err);
+                            return true; // Exit
+                        };
+                    }
+                    Err(e) => {
+                        match e {
+                            MessageHandleError::EmptyMsg => (),
+                            _ => {
+                                self.stats.new_unknown_message();
+                                tracing::error!("{e}: {data}");
+                                let _ = self.notification_sender.send(
+                                    RelayPoolNotification::MessageError {
+                                        url: self.url(),
+                                        error: e.to_string(),
+                                        raw: data,
+                                    },
+                                );
+                            }
+                        };
+                    }
+                }
+            }
+            Err(err) => tracing::error!("{}", err),
+        }
+
+        false
+    }
+
+    /// Register a channel fulfilled directly by the message thread when the `OK` of the [`EventId`] is received
+    ///
+    /// The receiver gets the status and the message of the `OK`. Unlike [`RelayPool::notifications`], it can't lag,
+    /// so it's the reliable way to wait for a publish confirmation.
+    /// Register it **before** sending the event, to not miss a fast `OK`. The channels of the dropped receivers
+    /// are removed when the `OK` is received, or when the same [`EventId`] is registered again.
+    pub async fn register_ok(&self, id: EventId) -> oneshot::Receiver<(bool, String)> {
+        let (tx, rx) = oneshot::channel();
+        let mut ok_senders = self.ok_senders.lock().await;
+        let senders = ok_senders.entry(id).or_default();
+        senders.retain(|sender| !sender.is_closed());
+        senders.push(tx);
+        rx
+    }
+
+    /// Remove the channels of the [`EventId`] no longer awaited
+    async fn unregister_ok(&self, id: &EventId) {
+        let mut ok_senders = self.ok_senders.lock().await;
+        if let Some(senders) = ok_senders.get_mut(id) {
+            senders.retain(|sender| !sender.is_closed());
+            if senders.is_empty() {
+                ok_senders.remove(id);
+            }
+        }
+    }
+
+    async fn fulfill_ok(&self, id: &EventId, status: bool, message: &str) {
+        let mut ok_senders = self.ok_senders.lock().await;
+        if let Some(senders) = ok_senders.remove(id) {
+            for sender in senders.into_iter() {
+                let _ = sender.send((status, message.to_string()));
+            }
+        }
+    }
+
+    /// Queue [`RelayEvent`], applying the [`QueueOverflowPolicy`] if the queue is full
+    async fn send_relay_event(
         &self,
         relay_msg: RelayEvent,
         sender: Option<oneshot::Sender<bool>>,
     ) -> Result<(), Error> {
-        self.relay_sender
-            .try_send((relay_msg, sender))
-            .map_err(|_| Error::MessageNotSent)
+        match self.opts.get_queue_overflow_policy() {
+            QueueOverflowPolicy::Error => self
+                .relay_sender
+                .try_send((relay_msg, sender, Timestamp::now()))
+                .map_err(|_| Error::MessageNotSent),
+            QueueOverflowPolicy::Block => self
+                .relay_sender
+                .send((relay_msg, sender, Timestamp::now()))
+                .await
+                .map_err(|_| Error::MessageNotSent),
+            QueueOverflowPolicy::DropOldest => {
+                match self
+                    .relay_sender
+                    .try_send((relay_msg, sender, Timestamp::now()))
+                {
+                    Ok(()) => Ok(()),
+                    Err(TrySendError::Full(msg)) => {
+                        match self.relay_receiver.try_lock() {
+                            // Event thread not running: drop the oldest message here
+                            Ok(mut rx) => {
+                                if let Ok((_, Some(sender), _)) = rx.try_recv() {
+                                    let _ = sender.send(false);
+                                }
+                            }
+                            // Let the event thread skip it
+                            Err(_) => {
+                                self.drop_oldest.fetch_add(1, Ordering::SeqCst);
+                            }
+                        }
+                        tracing::warn!("Queue full for {}: oldest message dropped", self.url);
+                        self.relay_sender
+                            .send(msg)
+                            .await
+                            .map_err(|_| Error::MessageNotSent)
+                    }
+                    Err(TrySendError::Closed(_)) => Err(Error::MessageNotSent),
+                }
+            }
+        }
+    }
+
+    /// Check if a queued [`RelayEvent`] must be dropped, according to [`QueueOverflowPolicy::DropOldest`]
+    ///
+    /// Only the messages can be dropped: close, stop and terminate events are always processed.
+    fn must_drop_oldest(&self, relay_event: &RelayEvent) -> bool {
+        if let RelayEvent::SendMsg(_) | RelayEvent::Batch(_) | RelayEvent::Ping = relay_event {
+            self.drop_oldest
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+        } else {
+            false
+        }
+    }
+
+    /// Check if a queued message is older than [`RelayOptions::max_queued_age`]
+    fn is_expired(&self, relay_event: &RelayEvent, queued_at: Timestamp) -> bool {
+        if let RelayEvent::SendMsg(_) | RelayEvent::Batch(_) = relay_event {
+            if let Some(max_age) = self.opts.get_max_queued_age() {
+                let age: u64 = Timestamp::now().as_u64().saturating_sub(queued_at.as_u64());
+                return age > max_age.as_secs();
+            }
+        }
+        false
+    }
+
+    /// Check if no data has been received for [`RelayOptions::stale_timeout`] while subscriptions are active
+    async fn is_stale(&self) -> bool {
+        let timeout: Duration = match self.opts.get_stale_timeout() {
+            Some(timeout) => timeout,
+            None => return false,
+        };
+
+        if !self.is_connected().await || self.subscriptions.lock().await.is_empty() {
+            return false;
+        }
+
+        let last: Timestamp = self.stats.last_received_at().max(self.stats.connected_at());
+        let elapsed: u64 = Timestamp::now().as_u64().saturating_sub(last.as_u64());
+        elapsed >= timeout.as_secs()
+    }
+
+    /// Check if the connection has been closed by [`RelayOptions::idle_timeout`] and is waiting for the next message
+    pub fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::SeqCst)
+    }
+
+    /// Get the UNIX timestamp of the last message sent (or of the last connection, if later)
+    pub fn last_activity_at(&self) -> Timestamp {
+        Timestamp::from(self.last_activity_at.load(Ordering::SeqCst)).max(self.stats.connected_at())
+    }
+
+    async fn is_idle_timeout_expired(&self) -> bool {
+        let timeout: Duration = match self.opts.get_idle_timeout() {
+            Some(timeout) => timeout,
+            None => return false,
+        };
+
+        if !self.is_connected().await
+            || self
+                .subscriptions
+                .lock()
+                .await
+                .values()
+                .any(|sub| !sub.paused)
+        {
+            return false;
+        }
+
+        let elapsed: u64 = Timestamp::now()
+            .as_u64()
+            .saturating_sub(self.last_activity_at().as_u64());
+        elapsed >= timeout.as_secs()
+    }
+
+    /// Save the outgoing activity, waking up the auto connect loop if idle
+    fn new_activity(&self) {
+        self.last_activity_at
+            .store(Timestamp::now().as_u64(), Ordering::SeqCst);
+        if self.idle.swap(false, Ordering::SeqCst) {
+            tracing::debug!("Waking up idle relay {}", self.url);
+            self.wake.notify_one();
+        }
     }
 
     /// Disconnect from relay and set status to 'Disconnected'
-    async fn disconnect(&self) -> Result<(), Error> {
+    async fn disconnect(&self, reason: DisconnectReason) -> Result<(), Error> {
         let status = self.status().await;
         if status.ne(&RelayStatus::Disconnected)
             && status.ne(&RelayStatus::Stopped)
             && status.ne(&RelayStatus::Terminated)
         {
-            self.send_relay_event(RelayEvent::Close, None)?;
+            self.send_relay_event(RelayEvent::Close(reason), None)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Close the current connection and set status to 'Disconnected'
+    ///
+    /// Unlike [`Relay::stop`], the auto connect loop isn't terminated: it reconnects on its next iteration.
+    /// If the relay is initialized, stopped or terminated, it's connected again with [`Relay::connect`].
+    pub async fn reconnect(&self) -> Result<(), Error> {
+        match self.status().await {
+            RelayStatus::Initialized | RelayStatus::Stopped | RelayStatus::Terminated => {
+                self.connect(false).await;
+            }
+            RelayStatus::Connected => {
+                self.send_relay_event(RelayEvent::Close(DisconnectReason::UserRequested), None)
+                    .await?;
+                self.set_status(
+                    RelayStatus::Disconnected,
+                    Some(DisconnectReason::UserRequested),
+                )
+                .await;
+            }
+            RelayStatus::Connecting | RelayStatus::Disconnected => (),
         }
         Ok(())
     }
@@ -805,7 +3229,7 @@ impl Relay {
             && status.ne(&RelayStatus::Stopped)
             && status.ne(&RelayStatus::Terminated)
         {
-            self.send_relay_event(RelayEvent::Stop, None)?;
+            self.send_relay_event(RelayEvent::Stop, None).await?;
         }
         Ok(())
     }
@@ -818,186 +3242,558 @@ impl Relay {
             && status.ne(&RelayStatus::Stopped)
             && status.ne(&RelayStatus::Terminated)
         {
-            self.send_relay_event(RelayEvent::Terminate, None)?;
+            self.send_relay_event(RelayEvent::Terminate, None).await?;
         }
         Ok(())
     }
 
-    /// Send msg to relay
-    pub async fn send_msg(&self, msg: ClientMessage, wait: Option<Duration>) -> Result<(), Error> {
-        if !self.opts.write() {
-            if let ClientMessage::Event(_) = msg {
-                return Err(Error::WriteDisabled);
-            }
+    /// Check if read actions are allowed
+    ///
+    /// Return `Ok(false)` if they must be skipped, according to [`DisabledPolicy::Skip`].
+    fn read_allowed(&self) -> Result<bool, Error> {
+        self.check_allowed(self.opts.read(), Error::ReadDisabled)
+    }
+
+    /// Check if write actions are allowed
+    ///
+    /// Return `Ok(false)` if they must be skipped, according to [`DisabledPolicy::Skip`].
+    fn write_allowed(&self) -> Result<bool, Error> {
+        self.check_allowed(self.opts.write(), Error::WriteDisabled)
+    }
+
+    fn check_allowed(&self, allowed: bool, error: Error) -> Result<bool, Error> {
+        if allowed {
+            return Ok(true);
+        }
+        match self.opts.get_disabled_policy() {
+            DisabledPolicy::Error => Err(error),
+            DisabledPolicy::Skip => Ok(false),
+        }
+    }
+
+    /// Check that the messages are allowed by the read/write [`RelayOptions`]
+    ///
+    /// Return `Ok(false)` if the messages must be skipped, according to [`DisabledPolicy::Skip`].
+    fn check_permissions<'a, I>(&self, msgs: I) -> Result<bool, Error>
+    where
+        I: IntoIterator<Item = &'a ClientMessage>,
+    {
+        for msg in msgs.into_iter() {
+            if msg.is_event() && !self.write_allowed()? {
+                return Ok(false);
+            }
+
+            if (msg.is_req() || msg.is_close()) && !self.read_allowed()? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Send [`RelayEvent`] and wait until it's written to the socket
+    async fn wait_written(
+        &self,
+        relay_event: RelayEvent,
+        timeout: Option<Duration>,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel::<bool>();
+        let res = time::timeout(timeout, async {
+            self.send_relay_event(relay_event, Some(tx)).await?;
+            rx.await.map_err(|_| Error::OneShotRecvError)
+        })
+        .await;
+        match res {
+            Some(result) => {
+                if result? {
+                    Ok(())
+                } else {
+                    Err(Error::MessageNotSent)
+                }
+            }
+            _ => Err(Error::RecvTimeout),
+        }
+    }
+
+    /// Send msg to relay
+    ///
+    /// If `wait` is set, wait until the message is written to the socket ([`SendConfirmation::Written`]):
+    /// this doesn't mean that the relay received or accepted it.
+    pub async fn send_msg(&self, msg: ClientMessage, wait: Option<Duration>) -> Result<(), Error> {
+        if !self.check_permissions([&msg])? {
+            return Ok(());
+        }
+
+        self.acquire_send_tokens([&msg], wait).await?;
+        self.new_activity();
+
+        self.reset_live_feeds([&msg]).await;
+
+        match wait {
+            Some(timeout) => {
+                self.wait_written(RelayEvent::SendMsg(Box::new(msg)), Some(timeout))
+                    .await
+            }
+            None => {
+                let relay_event = RelayEvent::SendMsg(Box::new(msg));
+                #[cfg(not(target_arch = "wasm32"))]
+                let relay_event = match self.send_to_extra_socket(relay_event).await {
+                    Some(relay_event) => relay_event,
+                    None => return Ok(()),
+                };
+                self.send_relay_event(relay_event, None).await
+            }
+        }
+    }
+
+    /// Wait for a token of [`RelayOptions::rate_limit`] for every `EVENT` message, up to `wait`
+    async fn acquire_send_tokens<'a, I>(&self, msgs: I, wait: Option<Duration>) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = &'a ClientMessage>,
+    {
+        let (rate, burst) = match self.opts.get_rate_limit() {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let n: usize = msgs
+            .into_iter()
+            .filter(|msg| matches!(msg, ClientMessage::Event(..)))
+            .count();
+        if n == 0 {
+            return Ok(());
+        }
+
+        time::timeout(wait, async {
+            loop {
+                let delay: Duration = {
+                    let mut bucket = self.rate_limiter.lock().await;
+                    bucket.refill(rate, burst);
+                    // Batches bigger than the burst wait for a full bucket
+                    let needed: f64 = (n as f64).min(burst as f64);
+                    if bucket.tokens >= needed {
+                        bucket.tokens -= n as f64;
+                        break;
+                    }
+                    Duration::from_secs_f64((needed - bucket.tokens) / rate)
+                };
+                tracing::debug!("Rate limit reached for {}: waiting {delay:?}", self.url);
+                thread::sleep(delay).await;
+            }
+        })
+        .await
+        .ok_or(Error::RateLimited)
+    }
+
+    /// Get the rate limit tokens currently available ([`RelayOptions::rate_limit`])
+    ///
+    /// Return `None` if the rate limit is not set.
+    pub async fn available_tokens(&self) -> Option<f64> {
+        let (rate, burst) = self.opts.get_rate_limit()?;
+        let mut bucket = self.rate_limiter.lock().await;
+        bucket.refill(rate, burst);
+        Some(bucket.tokens)
+    }
+
+    /// Send multiple [`ClientMessage`] at once
+    ///
+    /// If `wait` is set, wait until the messages are written to the socket ([`SendConfirmation::Written`]).
+    pub async fn batch_msg(
+        &self,
+        msgs: Vec<ClientMessage>,
+        wait: Option<Duration>,
+    ) -> Result<(), Error> {
+        if !self.check_permissions(&msgs)? {
+            return Ok(());
+        }
+
+        self.acquire_send_tokens(&msgs, wait).await?;
+        self.new_activity();
+
+        self.reset_live_feeds(&msgs).await;
+
+        match wait {
+            Some(timeout) => {
+                self.wait_written(RelayEvent::Batch(msgs), Some(timeout))
+                    .await
+            }
+            None => {
+                let relay_event = RelayEvent::Batch(msgs);
+                #[cfg(not(target_arch = "wasm32"))]
+                let relay_event = match self.send_to_extra_socket(relay_event).await {
+                    Some(relay_event) => relay_event,
+                    None => return Ok(()),
+                };
+                self.send_relay_event(relay_event, None).await
+            }
+        }
+    }
+
+    /// Send event and wait for `OK` relay msg
+    ///
+    /// [`RelaySendOptions::confirmation`] sets how far to wait (default: [`SendConfirmation::Acknowledged`]).
+    /// If the relay disconnects before the `OK`, return [`Error::Disconnected`] (or [`Error::ClosedByRelay`]) without waiting for the timeout.
+    pub async fn send_event(&self, event: Event, opts: RelaySendOptions) -> Result<EventId, Error> {
+        Ok(self.send_event_with_output(event, opts).await?.id)
+    }
+
+    /// Send event and wait for `OK` relay msg
+    #[cfg(feature = "blocking")]
+    pub fn send_event_blocking(
+        &self,
+        event: Event,
+        opts: RelaySendOptions,
+    ) -> Result<EventId, Error> {
+        RUNTIME.block_on(async { self.send_event(event, opts).await })
+    }
+
+    /// Send event without waiting for the `OK` relay msg
+    ///
+    /// Return immediately a receiver of the [`Relay::send_event`] result, that can be awaited later or ignored.
+    /// Useful to publish to many relays and collect the results at the end.
+    pub fn send_event_async(
+        &self,
+        event: Event,
+        opts: RelaySendOptions,
+    ) -> oneshot::Receiver<Result<EventId, Error>> {
+        let (tx, rx) = oneshot::channel();
+        let relay = self.clone();
+        thread::spawn(async move {
+            let res = relay.send_event(event, opts).await;
+            let _ = tx.send(res);
+        });
+        rx
+    }
+
+    /// Send event and wait for `OK` relay msg, returning also the message of the relay
+    ///
+    /// The message can contain advisories (i.e. `rate-limited: slow down`) also if the event was accepted.
+    /// It's empty if [`RelaySendOptions::confirmation`] is not [`SendConfirmation::Acknowledged`].
+    pub async fn send_event_with_output(
+        &self,
+        event: Event,
+        opts: RelaySendOptions,
+    ) -> Result<SendEventOutput, Error> {
+        if !self.write_allowed()? {
+            return Ok(SendEventOutput::new(event.id));
         }
 
-        if !self.opts.read() {
-            if let ClientMessage::Req { .. } | ClientMessage::Close(_) = msg {
-                return Err(Error::ReadDisabled);
+        self.check_pow(&event).await?;
+
+        let mut attempt: u8 = 0;
+        loop {
+            match self.send_event_once(event.clone(), opts).await {
+                Err(Error::EventNotPublished(message))
+                    if attempt < opts.retries && self.is_transient_rejection(&message) =>
+                {
+                    attempt += 1;
+                    tracing::warn!(
+                        "Event {} rejected by {}: {message}. Retrying ({attempt}/{})...",
+                        event.id,
+                        self.url,
+                        opts.retries
+                    );
+                    thread::sleep(opts.retry_delay).await;
+                }
+                res => return res,
             }
         }
+    }
 
-        match wait {
-            Some(timeout) => {
-                let (tx, rx) = oneshot::channel::<bool>();
-                self.send_relay_event(RelayEvent::SendMsg(Box::new(msg)), Some(tx))?;
-                match time::timeout(Some(timeout), rx).await {
-                    Some(result) => match result {
-                        Ok(val) => {
-                            if val {
-                                Ok(())
+    /// Check if an `OK` rejection message is transient, according to its NIP-01 prefix
+    ///
+    /// `rate-limited:` and `error:` are transient. `auth-required:` is transient only if
+    /// [`RelayOptions::auto_auth`] is set. Messages without a known transient prefix are considered permanent.
+    fn is_transient_rejection(&self, message: &str) -> bool {
+        message.starts_with("rate-limited:")
+            || message.starts_with("error:")
+            || (message.starts_with("auth-required:") && self.opts.get_auth_keys().is_some())
+    }
+
+    async fn send_event_once(
+        &self,
+        event: Event,
+        opts: RelaySendOptions,
+    ) -> Result<SendEventOutput, Error> {
+        let id: EventId = event.id;
+        let msg = ClientMessage::new_event(event);
+        let res = time::timeout(
+            opts.timeout.or(self.opts.get_default_send_timeout()),
+            async {
+                match opts.confirmation {
+                    SendConfirmation::Queued => {
+                        self.send_msg(msg, None).await?;
+                        return Ok(SendEventOutput::new(id));
+                    }
+                    SendConfirmation::Written => {
+                        self.check_permissions([&msg])?;
+                        self.acquire_send_tokens([&msg], None).await?;
+                        self.new_activity();
+                        self.wait_written(RelayEvent::SendMsg(Box::new(msg)), None)
+                            .await?;
+                        return Ok(SendEventOutput::new(id));
+                    }
+                    SendConfirmation::Acknowledged => {
+                        if self.is_read_closed() {
+                            return Err(Error::ReadClosed);
+                        }
+                    }
+                }
+                // The `OK` is received on a dedicated channel, to not miss it if the notifications lag
+                let mut ok = self.register_ok(id).await;
+                let mut notifications = self.notification_sender.subscribe();
+                self.send_msg(msg, None).await?;
+                loop {
+                    tokio::select! {
+                        res = &mut ok => {
+                            let (status, message) = res.map_err(|_| Error::LoopTerminated)?;
+                            if status {
+                                return Ok(SendEventOutput { id, message });
                             } else {
-                                Err(Error::MessageNotSent)
+                                return Err(Error::EventNotPublished(message));
                             }
                         }
-                        Err(_) => Err(Error::OneShotRecvError),
-                    },
-                    _ => Err(Error::RecvTimeout),
+                        notification = recv_notification(&mut notifications) => match notification {
+                            // Fail fast if the relay explains the failure with a `NOTICE`
+                            Some(RelayPoolNotification::Message(
+                                url,
+                                RelayMessage::Notice { message },
+                            )) => {
+                                if self.url == url && message.contains(&id.to_hex()) {
+                                    return Err(Error::Notice(message));
+                                }
+                            }
+                            // Fail fast if the relay disconnects before the `OK`
+                            Some(notification) => {
+                                if let Some(e) = self.disconnection_error(&notification) {
+                                    return Err(e);
+                                }
+                            }
+                            None => return Err(Error::LoopTerminated),
+                        }
+                    }
                 }
+            },
+        )
+        .await;
+        self.unregister_ok(&id).await;
+        res.ok_or(Error::Timeout)?
+    }
+
+    /// Get the error of a disconnection of this relay, if `notification` is one
+    fn disconnection_error(&self, notification: &RelayPoolNotification) -> Option<Error> {
+        if let RelayPoolNotification::RelayStatus {
+            url,
+            status,
+            reason,
+            ..
+        } = notification
+        {
+            if &self.url == url
+                && matches!(
+                    status,
+                    RelayStatus::Disconnected | RelayStatus::Stopped | RelayStatus::Terminated
+                )
+            {
+                let reason: DisconnectReason =
+                    reason.clone().unwrap_or(DisconnectReason::ConnectionLost);
+                return Some(reason.as_error().unwrap_or(Error::Disconnected(reason)));
             }
-            None => self.send_relay_event(RelayEvent::SendMsg(Box::new(msg)), None),
         }
+        None
     }
 
-    /// Send multiple [`ClientMessage`] at once
-    pub async fn batch_msg(
+    /// Send multiple [`Event`] at once
+    ///
+    /// If the relay disconnects before all the `OK`s, return [`Error::Disconnected`] (or [`Error::ClosedByRelay`])
+    /// without waiting for the timeout.
+    pub async fn batch_event(
         &self,
-        msgs: Vec<ClientMessage>,
-        wait: Option<Duration>,
+        events: Vec<Event>,
+        opts: RelaySendOptions,
     ) -> Result<(), Error> {
-        if !self.opts.write() && msgs.iter().any(|msg| msg.is_event()) {
-            return Err(Error::WriteDisabled);
-        }
+        self.batch_event_with_progress(events, opts, |_, _| {})
+            .await
+    }
 
-        if !self.opts.read() && msgs.iter().any(|msg| msg.is_req() || msg.is_close()) {
-            return Err(Error::ReadDisabled);
-        }
+    /// Send multiple [`Event`] at once
+    #[cfg(feature = "blocking")]
+    pub fn batch_event_blocking(
+        &self,
+        events: Vec<Event>,
+        opts: RelaySendOptions,
+    ) -> Result<(), Error> {
+        RUNTIME.block_on(async { self.batch_event(events, opts).await })
+    }
 
-        match wait {
-            Some(timeout) => {
-                let (tx, rx) = oneshot::channel::<bool>();
-                self.send_relay_event(RelayEvent::Batch(msgs), Some(tx))?;
-                match time::timeout(Some(timeout), rx).await {
-                    Some(result) => match result {
-                        Ok(val) => {
-                            if val {
-                                Ok(())
-                            } else {
-                                Err(Error::MessageNotSent)
-                            }
-                        }
-                        Err(_) => Err(Error::OneShotRecvError),
-                    },
-                    _ => Err(Error::RecvTimeout),
-                }
-            }
-            None => self.send_relay_event(RelayEvent::Batch(msgs), None),
-        }
+    /// Send multiple [`Event`] at once, reporting the progress
+    ///
+    /// Events are sent in chunks of [`RelaySendOptions::batch_size`]: after every chunk
+    /// `on_batch_progress` is called with the number of published events and the total.
+    /// The timeout is applied to the whole batch.
+    pub async fn batch_event_with_progress(
+        &self,
+        events: Vec<Event>,
+        opts: RelaySendOptions,
+        on_batch_progress: impl Fn(usize, usize),
+    ) -> Result<(), Error> {
+        self.batch_event_internal(events, opts, on_batch_progress, |_, _, _| {})
+            .await
     }
 
-    /// Send event and wait for `OK` relay msg
-    pub async fn send_event(&self, event: Event, opts: RelaySendOptions) -> Result<EventId, Error> {
-        let id: EventId = event.id;
-        time::timeout(opts.timeout, async {
-            self.send_msg(ClientMessage::new_event(event), None).await?;
-            let mut notifications = self.notification_sender.subscribe();
-            while let Ok(notification) = notifications.recv().await {
-                if let RelayPoolNotification::Message(
-                    url,
-                    RelayMessage::Ok {
-                        event_id,
-                        status,
-                        message,
-                    },
-                ) = notification
-                {
-                    if self.url == url && id == event_id {
-                        if status {
-                            return Ok(event_id);
-                        } else {
-                            return Err(Error::EventNotPublished(message));
-                        }
-                    }
-                }
-            }
-            Err(Error::LoopTerminated)
-        })
-        .await
-        .ok_or(Error::Timeout)?
+    /// Send multiple [`Event`] at once, calling `callback` for every `OK` relay msg as soon as received
+    ///
+    /// The callback receives the [`EventId`], the status and the message of the `OK`.
+    /// It's not called when [`RelaySendOptions::confirmation`] doesn't wait for the `OK`.
+    pub async fn batch_event_with_callback(
+        &self,
+        events: Vec<Event>,
+        opts: RelaySendOptions,
+        callback: impl Fn(EventId, bool, String),
+    ) -> Result<(), Error> {
+        self.batch_event_internal(events, opts, |_, _| {}, callback)
+            .await
     }
 
-    /// Send multiple [`Event`] at once
-    pub async fn batch_event(
+    async fn batch_event_internal(
         &self,
         events: Vec<Event>,
         opts: RelaySendOptions,
+        on_batch_progress: impl Fn(usize, usize),
+        on_ok: impl Fn(EventId, bool, String),
     ) -> Result<(), Error> {
         if events.is_empty() {
             return Err(Error::BatchEventEmpty);
         }
 
-        let msgs: Vec<ClientMessage> = events
-            .iter()
-            .cloned()
-            .map(ClientMessage::new_event)
-            .collect();
-        time::timeout(opts.timeout, async {
-            self.batch_msg(msgs, None).await?;
-            let mut missing: HashSet<EventId> = events.into_iter().map(|e| e.id).collect();
-            let mut published: HashSet<EventId> = HashSet::new();
-            let mut not_published: HashMap<EventId, String> = HashMap::new();
-            let mut notifications = self.notification_sender.subscribe();
-            while let Ok(notification) = notifications.recv().await {
-                if let RelayPoolNotification::Message(
-                    url,
-                    RelayMessage::Ok {
-                        event_id,
-                        status,
-                        message,
-                    },
-                ) = notification
-                {
-                    if self.url == url && missing.remove(&event_id) {
-                        if status {
-                            published.insert(event_id);
-                        } else {
-                            not_published.insert(event_id, message);
+        if !self.write_allowed()? {
+            return Ok(());
+        }
+
+        let total: usize = events.len();
+        let res = time::timeout(
+            opts.timeout.or(self.opts.get_default_send_timeout()),
+            async {
+                let mut published: HashSet<EventId> = HashSet::new();
+                let mut not_published: HashMap<EventId, String> = HashMap::new();
+
+                for chunk in events.chunks(opts.batch_size.max(1)) {
+                    let msgs: Vec<ClientMessage> = chunk
+                        .iter()
+                        .cloned()
+                        .map(ClientMessage::new_event)
+                        .collect();
+                    match opts.confirmation {
+                        SendConfirmation::Queued => {
+                            self.batch_msg(msgs, None).await?;
+                            published.extend(chunk.iter().map(|e| e.id));
+                            on_batch_progress(published.len(), total);
+                            continue;
+                        }
+                        SendConfirmation::Written => {
+                            self.check_permissions(&msgs)?;
+                            self.acquire_send_tokens(&msgs, None).await?;
+                            self.new_activity();
+                            self.wait_written(RelayEvent::Batch(msgs), None).await?;
+                            published.extend(chunk.iter().map(|e| e.id));
+                            on_batch_progress(published.len(), total);
+                            continue;
+                        }
+                        SendConfirmation::Acknowledged => {
+                            if self.is_read_closed() {
+                                return Err(Error::ReadClosed);
+                            }
+                        }
+                    }
+                    let mut missing: HashSet<EventId> = chunk.iter().map(|e| e.id).collect();
+                    // The `OK`s are received on dedicated channels, to not miss them if the notifications lag
+                    let mut oks = FuturesUnordered::new();
+                    for id in missing.iter().copied() {
+                        let ok = self.register_ok(id).await;
+                        oks.push(async move { (id, ok.await) });
+                    }
+                    let mut notifications = self.notification_sender.subscribe();
+                    self.batch_msg(msgs, None).await?;
+                    loop {
+                        tokio::select! {
+                            ok = oks.next() => {
+                                let (event_id, res) = match ok {
+                                    Some(ok) => ok,
+                                    None => break,
+                                };
+                                let (status, message) = res.map_err(|_| Error::LoopTerminated)?;
+                                if missing.remove(&event_id) {
+                                    on_ok(event_id, status, message.clone());
+                                    if status {
+                                        published.insert(event_id);
+                                    } else {
+                                        not_published.insert(event_id, message);
+                                    }
+                                }
+                            }
+                            // Fail fast if the relay disconnects before the `OK`s: they'll never be received
+                            notification = recv_notification(&mut notifications) => match notification {
+                                Some(notification) => {
+                                    if let Some(e) = self.disconnection_error(&notification) {
+                                        return Err(e);
+                                    }
+                                }
+                                None => return Err(Error::LoopTerminated),
+                            }
                         }
                     }
-                }
 
-                if missing.is_empty() {
-                    break;
+                    on_batch_progress(published.len(), total);
                 }
-            }
 
-            if !published.is_empty() && not_published.is_empty() {
-                Ok(())
-            } else if !published.is_empty() && !not_published.is_empty() {
-                Err(Error::PartialPublish {
-                    published: published.into_iter().collect(),
-                    not_published,
-                })
-            } else {
-                Err(Error::EventsNotPublished(not_published))
-            }
-        })
-        .await
-        .ok_or(Error::Timeout)?
+                if !published.is_empty() && not_published.is_empty() {
+                    Ok(())
+                } else if !published.is_empty() && !not_published.is_empty() {
+                    // Keep the order of submission
+                    let mut ordered: Vec<EventId> = Vec::with_capacity(published.len());
+                    for event in events.iter() {
+                        if published.remove(&event.id) {
+                            ordered.push(event.id);
+                        }
+                    }
+                    Err(Error::PartialPublish {
+                        published: ordered,
+                        not_published,
+                    })
+                } else {
+                    Err(Error::EventsNotPublished(not_published))
+                }
+            },
+        )
+        .await;
+        for event in events.iter() {
+            self.unregister_ok(&event.id).await;
+        }
+        res.ok_or(Error::Timeout)?
     }
 
     /// Subscribes relay with existing filter
-    async fn resubscribe_all(&self, wait: Option<Duration>) -> Result<(), Error> {
-        if !self.opts.read() {
-            return Err(Error::ReadDisabled);
+    pub async fn resubscribe_all(&self, wait: Option<Duration>) -> Result<(), Error> {
+        if !self.read_allowed()? {
+            return Ok(());
         }
 
         let subscriptions = self.subscriptions().await;
 
+        // New EOSE messages will be received for every subscription
+        self.pending_eose.lock().await.clear();
+
         for (internal_id, sub) in subscriptions.into_iter() {
+            if sub.paused {
+                continue;
+            }
             if !sub.filters.is_empty() {
-                self.send_msg(ClientMessage::new_req(sub.id.clone(), sub.filters), wait)
-                    .await?;
+                self.add_pending_eose(sub.id.clone()).await;
+                self.send_msg(
+                    ClientMessage::new_req(sub.id.clone(), sub.resubscribe_filters()),
+                    wait,
+                )
+                .await?;
+                self.notify_subscribed(internal_id, SubscribeReason::Resubscribed);
             } else {
                 tracing::warn!("Subscription '{internal_id}' has empty filters");
             }
@@ -1011,21 +3807,89 @@ impl Relay {
         internal_id: InternalSubscriptionId,
         wait: Option<Duration>,
     ) -> Result<(), Error> {
-        if !self.opts.read() {
-            return Err(Error::ReadDisabled);
+        if !self.read_allowed()? {
+            return Ok(());
+        }
+
+        let mut subscriptions = self.subscriptions.lock().await;
+        let sub = subscriptions
+            .get_mut(&internal_id)
+            .ok_or(Error::InternalIdNotFound)?;
+        // Sending the `REQ` resumes a paused subscription
+        sub.paused = false;
+        let sub: ActiveSubscription = sub.clone();
+        drop(subscriptions);
+
+        self.add_pending_eose(sub.id.clone()).await;
+        if let Err(e) = self
+            .send_msg(
+                ClientMessage::new_req(sub.id.clone(), sub.filters.clone()),
+                wait,
+            )
+            .await
+        {
+            self.remove_pending_eose(&sub.id).await;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn notify_subscribed(&self, internal_id: InternalSubscriptionId, reason: SubscribeReason) {
+        let _ = self
+            .notification_sender
+            .send(RelayPoolNotification::Subscribed {
+                url: self.url(),
+                internal_id,
+                reason,
+            });
+    }
+
+    fn notify_unsubscribed(&self, internal_id: InternalSubscriptionId) {
+        let _ = self
+            .notification_sender
+            .send(RelayPoolNotification::Unsubscribed {
+                url: self.url(),
+                internal_id,
+            });
+    }
+
+    async fn add_pending_eose(&self, id: SubscriptionId) {
+        let mut pending_eose = self.pending_eose.lock().await;
+        pending_eose.insert(id);
+    }
+
+    /// Remove subscription from the ones waiting for EOSE,
+    /// notifying [`RelayPoolNotification::AllCaughtUp`] if it was the last one
+    async fn remove_pending_eose(&self, id: &SubscriptionId) {
+        let mut pending_eose = self.pending_eose.lock().await;
+        if pending_eose.remove(id) && pending_eose.is_empty() {
+            tracing::debug!("All subscriptions caught up for {}", self.url);
+            let _ = self
+                .notification_sender
+                .send(RelayPoolNotification::AllCaughtUp { url: self.url() });
         }
+    }
 
+    /// Send the `REQ` for the staged filters of an [`ActiveSubscription`]
+    ///
+    /// Allow to apply multiple [`Relay::update_subscription_filters`] calls with a single `REQ`.
+    pub async fn commit_subscription(
+        &self,
+        internal_id: InternalSubscriptionId,
+        wait: Option<Duration>,
+    ) -> Result<(), Error> {
         let subscriptions = self.subscriptions().await;
         let sub = subscriptions
             .get(&internal_id)
             .ok_or(Error::InternalIdNotFound)?;
 
-        self.send_msg(
-            ClientMessage::new_req(sub.id.clone(), sub.filters.clone()),
-            wait,
-        )
-        .await?;
+        if sub.filters.is_empty() {
+            return Err(Error::FiltersEmpty);
+        }
 
+        self.resubscribe(internal_id.clone(), wait).await?;
+        self.notify_subscribed(internal_id, SubscribeReason::Updated);
         Ok(())
     }
 
@@ -1039,24 +3903,129 @@ impl Relay {
             .await
     }
 
+    /// Subscribe
+    #[cfg(feature = "blocking")]
+    pub fn subscribe_blocking(
+        &self,
+        filters: Vec<Filter>,
+        wait: Option<Duration>,
+    ) -> Result<(), Error> {
+        RUNTIME.block_on(async { self.subscribe(filters, wait).await })
+    }
+
     /// Subscribe with custom internal ID
+    ///
+    /// The subscription is kept open until [`Relay::unsubscribe_with_internal_id`].
+    /// If it already exists with the same filters, the `REQ` is not sent again.
+    ///
+    /// Fail with [`Error::TooManySubscriptions`] if the new subscription exceeds the `max_subscriptions` advertised by the relay.
     pub async fn subscribe_with_internal_id(
         &self,
         internal_id: InternalSubscriptionId,
         filters: Vec<Filter>,
         wait: Option<Duration>,
     ) -> Result<(), Error> {
-        if !self.opts.read() {
-            return Err(Error::ReadDisabled);
+        self.subscribe_with_internal_id_opts(
+            internal_id,
+            filters,
+            wait,
+            SubscribeOptions::default(),
+        )
+        .await
+    }
+
+    /// Subscribe with custom internal ID and [`SubscribeOptions`]
+    ///
+    /// If [`SubscribeOptions::filter_opts`] are set, the subscription is automatically closed after EOSE according to them,
+    /// otherwise it's kept open until [`Relay::unsubscribe_with_internal_id`].
+    ///
+    /// If the subscription already exists with the same filters and [`FilterOptions`], the `REQ` is not sent again,
+    /// unless [`SubscribeOptions::force`] is set.
+    ///
+    /// Fail with [`Error::TooManySubscriptions`] if the new subscription exceeds the `max_subscriptions` advertised by the relay.
+    pub async fn subscribe_with_internal_id_opts(
+        &self,
+        internal_id: InternalSubscriptionId,
+        filters: Vec<Filter>,
+        wait: Option<Duration>,
+        opts: SubscribeOptions,
+    ) -> Result<(), Error> {
+        let SubscribeOptions {
+            filter_opts: opts,
+            force,
+            id,
+        } = opts;
+
+        if !self.read_allowed()? {
+            return Ok(());
         }
 
         if filters.is_empty() {
             return Err(Error::FiltersEmpty);
         }
 
-        self.update_subscription_filters(internal_id.clone(), filters)
-            .await;
-        self.resubscribe(internal_id, wait).await
+        let new_subscription: bool = !self.subscriptions.lock().await.contains_key(&internal_id);
+        let filters: Vec<Filter> = self.apply_limitations(filters, new_subscription).await?;
+
+        if !force && !new_subscription {
+            let subscriptions = self.subscriptions.lock().await;
+            if let Some(sub) = subscriptions.get(&internal_id) {
+                if !sub.is_paused() && sub.filters == filters && sub.opts == opts {
+                    tracing::debug!(
+                        "Subscription {internal_id} unchanged for {}: REQ not sent",
+                        self.url
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        self.update_subscription_filters_checked(internal_id.clone(), filters, opts, id)
+            .await?;
+        self.resubscribe(internal_id.clone(), wait).await?;
+        let reason = if new_subscription {
+            SubscribeReason::Created
+        } else {
+            SubscribeReason::Updated
+        };
+        self.notify_subscribed(internal_id, reason);
+        Ok(())
+    }
+
+    /// Replace the filters of an existing subscription
+    ///
+    /// The `REQ` is sent with the same [`SubscriptionId`]: per NIP-01 the relay overwrites the previous filters,
+    /// so there isn't a window without an active subscription.
+    /// Unlike [`Relay::subscribe_with_internal_id`], fail with [`Error::InternalIdNotFound`] if the subscription doesn't exist.
+    pub async fn replace_subscription(
+        &self,
+        internal_id: InternalSubscriptionId,
+        filters: Vec<Filter>,
+    ) -> Result<(), Error> {
+        if !self.read_allowed()? {
+            return Ok(());
+        }
+
+        if filters.is_empty() {
+            return Err(Error::FiltersEmpty);
+        }
+
+        if !self.subscriptions.lock().await.contains_key(&internal_id) {
+            return Err(Error::InternalIdNotFound);
+        }
+
+        let filters: Vec<Filter> = self.apply_limitations(filters, false).await?;
+
+        let mut subscriptions = self.subscriptions.lock().await;
+        let sub = subscriptions
+            .get_mut(&internal_id)
+            .ok_or(Error::InternalIdNotFound)?;
+        sub.filters = filters;
+        drop(subscriptions);
+
+        self.resubscribe(internal_id.clone(), None).await?;
+        self.notify_subscribed(internal_id, SubscribeReason::Updated);
+        Ok(())
     }
 
     /// Unsubscribe
@@ -1071,38 +4040,141 @@ impl Relay {
         internal_id: InternalSubscriptionId,
         wait: Option<Duration>,
     ) -> Result<(), Error> {
-        if !self.opts.read() {
-            return Err(Error::ReadDisabled);
+        if !self.read_allowed()? {
+            return Ok(());
         }
 
-        let mut subscriptions = self.subscriptions().await;
+        let mut subscriptions = self.subscriptions.lock().await;
         let subscription = subscriptions
             .remove(&internal_id)
             .ok_or(Error::InternalIdNotFound)?;
+        drop(subscriptions);
+        self.remove_pending_eose(&subscription.id).await;
         self.send_msg(ClientMessage::close(subscription.id), wait)
             .await?;
+        self.notify_unsubscribed(internal_id);
+        Ok(())
+    }
+
+    /// Pause a subscription
+    ///
+    /// The `CLOSE` is sent, but the [`ActiveSubscription`] is kept with its filters (and skipped by [`Relay::resubscribe_all`]),
+    /// until [`Relay::resume_subscription`]. Do nothing if already paused.
+    pub async fn pause_subscription(
+        &self,
+        internal_id: InternalSubscriptionId,
+    ) -> Result<(), Error> {
+        if !self.read_allowed()? {
+            return Ok(());
+        }
+
+        let mut subscriptions = self.subscriptions.lock().await;
+        let sub = subscriptions
+            .get_mut(&internal_id)
+            .ok_or(Error::InternalIdNotFound)?;
+        if sub.paused {
+            return Ok(());
+        }
+        sub.paused = true;
+        let id: SubscriptionId = sub.id.clone();
+        drop(subscriptions);
+
+        self.remove_pending_eose(&id).await;
+        self.send_msg(ClientMessage::close(id), None).await?;
+        self.notify_unsubscribed(internal_id);
+        Ok(())
+    }
+
+    /// Resume a subscription paused with [`Relay::pause_subscription`], sending again its `REQ`
+    ///
+    /// Do nothing if not paused.
+    pub async fn resume_subscription(
+        &self,
+        internal_id: InternalSubscriptionId,
+    ) -> Result<(), Error> {
+        if !self.read_allowed()? {
+            return Ok(());
+        }
+
+        let subscriptions = self.subscriptions.lock().await;
+        let sub = subscriptions
+            .get(&internal_id)
+            .ok_or(Error::InternalIdNotFound)?;
+        if !sub.paused {
+            return Ok(());
+        }
+        drop(subscriptions);
+
+        self.resubscribe(internal_id.clone(), None).await?;
+        self.notify_subscribed(internal_id, SubscribeReason::Resubscribed);
         Ok(())
     }
 
     /// Unsubscribe from all subscriptions
+    ///
+    /// The subscriptions are removed, so they are not sent again by [`Relay::resubscribe_all`].
+    /// The `CLOSE` is not sent for the paused ones, since already closed.
     pub async fn unsubscribe_all(&self, wait: Option<Duration>) -> Result<(), Error> {
-        if !self.opts.read() {
-            return Err(Error::ReadDisabled);
+        if !self.read_allowed()? {
+            return Ok(());
         }
 
-        let subscriptions = self.subscriptions().await;
+        let mut subscriptions = self.subscriptions.lock().await;
+        let removed: Vec<(InternalSubscriptionId, ActiveSubscription)> =
+            subscriptions.drain().collect();
+        drop(subscriptions);
 
-        for sub in subscriptions.into_values() {
-            self.send_msg(ClientMessage::close(sub.id.clone()), wait)
-                .await?;
+        for (internal_id, sub) in removed.into_iter() {
+            self.remove_pending_eose(&sub.id).await;
+            if !sub.is_paused() {
+                self.send_msg(ClientMessage::close(sub.id), wait).await?;
+            }
+            self.notify_unsubscribed(internal_id);
         }
 
         Ok(())
     }
 
+    /// Like [`Relay::handle_events_of`], but stop early when the [`CancelHandle`] is triggered
+    async fn handle_events_of_until_cancelled<F>(
+        &self,
+        id: SubscriptionId,
+        filters: &[Filter],
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+        callback: impl Fn(Event) -> F,
+        cancel: Option<&CancelHandle>,
+    ) -> Result<(), Error>
+    where
+        F: Future<Output = ()>,
+    {
+        match cancel {
+            Some(cancel) => {
+                if cancel.is_cancelled() {
+                    return Ok(());
+                }
+                tokio::select! {
+                    res = self.handle_events_of(id.clone(), filters, timeout, opts, callback) => res,
+                    _ = cancel.cancelled() => {
+                        tracing::debug!("Query {id} cancelled for {}", self.url);
+                        Ok(())
+                    }
+                }
+            }
+            None => {
+                self.handle_events_of(id, filters, timeout, opts, callback)
+                    .await
+            }
+        }
+    }
+
+    /// Handle the events of a `get_events_of` subscription
+    ///
+    /// `filters` are used only to check the events if [`RelayOptions::enforce_filter_match`] is enabled.
     async fn handle_events_of<F>(
         &self,
         id: SubscriptionId,
+        filters: &[Filter],
         timeout: Option<Duration>,
         opts: FilterOptions,
         callback: impl Fn(Event) -> F,
@@ -1113,16 +4185,46 @@ impl Relay {
         let mut counter = 0;
         let mut received_eose: bool = false;
 
+        // Events already delivered, if `dedup_events` is enabled
+        let mut seen: Option<HashSet<EventId>> = if self.opts.get_dedup_events() {
+            Some(HashSet::new())
+        } else {
+            None
+        };
+
+        // Called right after sending the REQ
+        let sent_at = Instant::now();
+        let mut latency_saved: bool = false;
+
         let mut notifications = self.notification_sender.subscribe();
         time::timeout(timeout, async {
-            while let Ok(notification) = notifications.recv().await {
-                if let RelayPoolNotification::Message(_, msg) = notification {
+            while let Some(notification) = recv_notification(&mut notifications).await {
+                if let RelayPoolNotification::Message(url, msg) = notification {
+                    if !latency_saved {
+                        if let RelayMessage::Event {
+                            subscription_id, ..
+                        }
+                        | RelayMessage::EndOfStoredEvents(subscription_id) = &msg
+                        {
+                            if subscription_id.eq(&id) {
+                                self.stats.save_latency(sent_at.elapsed());
+                                latency_saved = true;
+                            }
+                        }
+                    }
                     match msg {
                         RelayMessage::Event {
                             subscription_id,
                             event,
                         } => {
-                            if subscription_id.eq(&id) {
+                            if self.accept_query_event(
+                                &url,
+                                &subscription_id,
+                                &event,
+                                &id,
+                                filters,
+                                &mut seen,
+                            ) {
                                 callback(*event).await;
                                 if let FilterOptions::WaitForEventsAfterEOSE(num) = opts {
                                     if received_eose {
@@ -1142,105 +4244,764 @@ impl Relay {
                                 );
                                 received_eose = true;
                                 if let FilterOptions::ExitOnEOSE
-                                | FilterOptions::WaitDurationAfterEOSE(_) = opts
+                                | FilterOptions::WaitDurationAfterEOSE(_)
+                                | FilterOptions::WaitAfterEOSE { .. } = opts
                                 {
                                     break;
                                 }
                             }
                         }
-                        RelayMessage::Ok { .. } => (),
-                        _ => {
-                            tracing::debug!("Receive unhandled message {msg:?} from {}", self.url)
+                        RelayMessage::Ok { .. } => (),
+                        RelayMessage::Notice { message }
+                            if url == self.url && message.contains(&id.to_string()) =>
+                        {
+                            return Err(Error::Notice(message));
+                        }
+                        RelayMessage::Closed {
+                            subscription_id,
+                            message,
+                        } if url == self.url && subscription_id == id => {
+                            return Err(Error::SubscriptionClosed { reason: message });
+                        }
+                        _ => {
+                            tracing::debug!("Receive unhandled message {msg:?} from {}", self.url)
+                        }
+                    };
+                }
+            }
+            Ok(())
+        })
+        .await
+        .ok_or(Error::Timeout)??;
+
+        if let FilterOptions::WaitDurationAfterEOSE(duration) = opts {
+            time::timeout(Some(duration), async {
+                while let Some(notification) = recv_notification(&mut notifications).await {
+                    if let RelayPoolNotification::Message(
+                        url,
+                        RelayMessage::Event {
+                            subscription_id,
+                            event,
+                        },
+                    ) = notification
+                    {
+                        if self.accept_query_event(
+                            &url,
+                            &subscription_id,
+                            &event,
+                            &id,
+                            filters,
+                            &mut seen,
+                        ) {
+                            callback(*event).await;
+                        }
+                    }
+                }
+            })
+            .await;
+        }
+
+        if let FilterOptions::WaitAfterEOSE {
+            max_events,
+            max_duration,
+        } = opts
+        {
+            // Stop at the first between the events cap and the duration timer
+            let mut counter: usize = 0;
+            if max_events > 0 {
+                time::timeout(Some(max_duration), async {
+                    while let Some(notification) = recv_notification(&mut notifications).await {
+                        if let RelayPoolNotification::Message(
+                            url,
+                            RelayMessage::Event {
+                                subscription_id,
+                                event,
+                            },
+                        ) = notification
+                        {
+                            if self.accept_query_event(
+                                &url,
+                                &subscription_id,
+                                &event,
+                                &id,
+                                filters,
+                                &mut seen,
+                            ) {
+                                callback(*event).await;
+                                counter += 1;
+                                if counter >= max_events {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                })
+                .await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check if an event received while handling a `get_events_of` subscription must be passed to the callback
+    ///
+    /// The event must be received from this relay for the subscription `id`, not be already delivered
+    /// (if [`RelayOptions::dedup_events`]) and match the filters (if [`RelayOptions::enforce_filter_match`]).
+    fn accept_query_event(
+        &self,
+        url: &Url,
+        subscription_id: &SubscriptionId,
+        event: &Event,
+        id: &SubscriptionId,
+        filters: &[Filter],
+        seen: &mut Option<HashSet<EventId>>,
+    ) -> bool {
+        if url != &self.url || subscription_id != id {
+            return false;
+        }
+        if let Some(seen) = seen {
+            if !seen.insert(event.id) {
+                return false;
+            }
+        }
+        !self.opts.get_enforce_filter_match() || self.match_filters(filters, id, event)
+    }
+
+    /// Get events of filters with custom callback
+    pub async fn get_events_of_with_callback<F>(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+        callback: impl Fn(Event) -> F,
+    ) -> Result<(), Error>
+    where
+        F: Future<Output = ()>,
+    {
+        self.events_of_with_callback(filters, timeout, opts, callback, None)
+            .await
+    }
+
+    async fn events_of_with_callback<F>(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+        callback: impl Fn(Event) -> F,
+        cancel: Option<&CancelHandle>,
+    ) -> Result<(), Error>
+    where
+        F: Future<Output = ()>,
+    {
+        if !self.read_allowed()? {
+            return Ok(());
+        }
+
+        let filters: Vec<Filter> = self.apply_limitations(filters, true).await?;
+
+        let _slot: QuerySlot = self.acquire_query_slot().await;
+
+        let id = SubscriptionId::generate();
+
+        self.send_msg(ClientMessage::new_req(id.clone(), filters.clone()), None)
+            .await?;
+
+        self.handle_events_of_until_cancelled(
+            id.clone(),
+            &filters,
+            timeout,
+            opts,
+            callback,
+            cancel,
+        )
+        .await?;
+
+        // Unsubscribe
+        self.send_msg(ClientMessage::close(id), None).await?;
+
+        Ok(())
+    }
+
+    /// Get events of filters
+    ///
+    /// The filters with more `authors` or `ids` than [`RelayOptions::split_threshold`] are split in multiple `REQ`,
+    /// sent one after the other: the `timeout` applies to all of them.
+    pub async fn get_events_of(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+    ) -> Result<Vec<Event>, Error> {
+        self.events_of(filters, timeout, opts, None).await
+    }
+
+    /// Get events of filters, stopping early when the [`CancelHandle`] is triggered
+    ///
+    /// The events received before the cancellation are returned.
+    pub async fn get_events_of_cancellable(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+        cancel: CancelHandle,
+    ) -> Result<Vec<Event>, Error> {
+        self.events_of(filters, timeout, opts, Some(&cancel)).await
+    }
+
+    /// Get events of filters created since a sync point
+    ///
+    /// The `since` of every filter is overridden. Return the events and the new sync point:
+    /// the max `created_at` of the received events, or `since` if no event is received.
+    pub async fn get_events_since(
+        &self,
+        filters: Vec<Filter>,
+        since: Timestamp,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+    ) -> Result<(Vec<Event>, Timestamp), Error> {
+        let filters: Vec<Filter> = filters.into_iter().map(|f| f.since(since)).collect();
+        let events: Vec<Event> = self.get_events_of(filters, timeout, opts).await?;
+        let last: Timestamp = events
+            .iter()
+            .map(|e| e.created_at)
+            .max()
+            .map_or(since, |created_at| created_at.max(since));
+        Ok((events, last))
+    }
+
+    async fn events_of(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+        cancel: Option<&CancelHandle>,
+    ) -> Result<Vec<Event>, Error> {
+        let key: Option<u64> = self
+            .opts
+            .get_query_cache_ttl()
+            .map(|_| query_cache_key(&filters, opts));
+
+        if let Some(key) = key {
+            if let Some(events) = self.cached_query(key).await {
+                self.stats.new_cache_hit();
+                return Ok(events);
+            }
+            self.stats.new_cache_miss();
+        }
+
+        let events: Vec<Event> = match self.opts.get_split_threshold() {
+            Some(threshold) => {
+                self.get_split_events_of(filters.clone(), timeout, opts, threshold.max(1), cancel)
+                    .await?
+            }
+            None => self.fetch_events_of(filters, timeout, opts, cancel).await?,
+        };
+
+        // Don't cache partial results
+        let cancelled: bool = cancel.map(|c| c.is_cancelled()).unwrap_or(false);
+        if let (Some(key), false) = (key, cancelled) {
+            self.cache_query(key, events.clone()).await;
+        }
+
+        Ok(events)
+    }
+
+    /// Get the events of a cached query, if not expired
+    async fn cached_query(&self, key: u64) -> Option<Vec<Event>> {
+        let ttl: Duration = self.opts.get_query_cache_ttl()?;
+        let mut query_cache = self.query_cache.lock().await;
+        match query_cache.get_mut(&key) {
+            Some(query) if query.cached_at.elapsed() < ttl => {
+                query.used_at = Instant::now();
+                Some(query.events.clone())
+            }
+            Some(_) => {
+                query_cache.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Cache the events of a query, evicting the least recently used one if the cache is full
+    async fn cache_query(&self, key: u64, events: Vec<Event>) {
+        let size: usize = self.opts.get_query_cache_size();
+        if size == 0 {
+            return;
+        }
+        let mut query_cache = self.query_cache.lock().await;
+        if !query_cache.contains_key(&key) && query_cache.len() >= size {
+            let lru: Option<u64> = query_cache
+                .iter()
+                .min_by_key(|(_, query)| query.used_at)
+                .map(|(key, _)| *key);
+            if let Some(lru) = lru {
+                query_cache.remove(&lru);
+            }
+        }
+        let now = Instant::now();
+        let events_size: usize = events.iter().map(|event| event.as_json().len()).sum();
+        query_cache.insert(
+            key,
+            CachedQuery {
+                events,
+                size: events_size,
+                cached_at: now,
+                used_at: now,
+            },
+        );
+        drop(query_cache);
+
+        self.enforce_memory_limit().await;
+    }
+
+    /// Wait for a free slot, if [`RelayOptions::max_concurrent_queries`] is set
+    async fn acquire_query_slot(&self) -> QuerySlot {
+        let permit: Option<OwnedSemaphorePermit> = match &self.query_semaphore {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        self.queries_in_flight.fetch_add(1, Ordering::SeqCst);
+        QuerySlot {
+            _permit: permit,
+            in_flight: self.queries_in_flight.clone(),
+        }
+    }
+
+    /// Get the number of one-shot queries (`get_events_of`, `req_events_of`) currently running
+    pub fn queries_in_flight(&self) -> usize {
+        self.queries_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Clear the `get_events_of` query cache
+    pub async fn clear_query_cache(&self) {
+        let mut query_cache = self.query_cache.lock().await;
+        query_cache.clear();
+    }
+
+    async fn fetch_events_of(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+        cancel: Option<&CancelHandle>,
+    ) -> Result<Vec<Event>, Error> {
+        let events: Mutex<Vec<Event>> = Mutex::new(Vec::new());
+        self.events_of_with_callback(
+            filters,
+            timeout,
+            opts,
+            |event| async {
+                let mut events = events.lock().await;
+                events.push(event);
+            },
+            cancel,
+        )
+        .await?;
+        Ok(events.into_inner())
+    }
+
+    /// Query the filters exceeding the threshold in chunks and merge the results, deduplicated by id
+    async fn get_split_events_of(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+        threshold: usize,
+        cancel: Option<&CancelHandle>,
+    ) -> Result<Vec<Event>, Error> {
+        let (large, small): (Vec<Filter>, Vec<Filter>) = filters
+            .into_iter()
+            .partition(|f| f.authors.len() > threshold || f.ids.len() > threshold);
+
+        // One deadline for all the `REQ`
+        let deadline: Option<Instant> = timeout.map(|timeout| Instant::now() + timeout);
+        let remaining =
+            || deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+        let mut ids: HashSet<EventId> = HashSet::new();
+        let mut events: Vec<Event> = Vec::new();
+
+        if !small.is_empty() {
+            for event in self
+                .fetch_events_of(small, remaining(), opts, cancel)
+                .await?
+            {
+                if ids.insert(event.id) {
+                    events.push(event);
+                }
+            }
+        }
+
+        for filter in large.into_iter() {
+            let limit: Option<usize> = filter.limit;
+            let mut filter_ids: HashSet<EventId> = HashSet::new();
+            let mut filter_events: Vec<Event> = Vec::new();
+            for chunk in split_filter(&filter, threshold).into_iter() {
+                let timeout: Option<Duration> = remaining();
+                if timeout.map_or(false, |timeout| timeout.is_zero()) {
+                    return Err(Error::Timeout);
+                }
+                for event in self
+                    .fetch_events_of(vec![chunk], timeout, opts, cancel)
+                    .await?
+                {
+                    if filter_ids.insert(event.id) {
+                        filter_events.push(event);
+                    }
+                }
+            }
+
+            // Each chunk returns up to `limit` events: keep only the newest ones
+            if let Some(limit) = limit {
+                filter_events.sort_by_key(|e| std::cmp::Reverse(e.created_at));
+                filter_events.truncate(limit);
+            }
+
+            for event in filter_events.into_iter() {
+                if ids.insert(event.id) {
+                    events.push(event);
+                }
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Get events of filters
+    #[cfg(feature = "blocking")]
+    pub fn get_events_of_blocking(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+    ) -> Result<Vec<Event>, Error> {
+        RUNTIME.block_on(async { self.get_events_of(filters, timeout, opts).await })
+    }
+
+    /// Stream events of filters
+    ///
+    /// Events are yielded as soon as received, without buffering all of them.
+    /// The stream ends when the timeout expires or according to [`FilterOptions`].
+    /// Dropping the stream closes the subscription.
+    pub fn stream_events_of(
+        &self,
+        filters: Vec<Filter>,
+        timeout: Option<Duration>,
+        opts: FilterOptions,
+    ) -> impl Stream<Item = Event> {
+        let (tx, rx) = mpsc::channel::<Event>(1024);
+        let relay = self.clone();
+        thread::spawn(async move {
+            let cancel = CancelHandle::new();
+            let mut query = Box::pin(relay.events_of_with_callback(
+                filters,
+                timeout,
+                opts,
+                |event| {
+                    let tx = tx.clone();
+                    let cancel = cancel.clone();
+                    async move {
+                        if tx.send(event).await.is_err() {
+                            cancel.cancel();
+                        }
+                    }
+                },
+                Some(&cancel),
+            ));
+            let res = tokio::select! {
+                res = &mut query => res,
+                // Stream dropped: stop also if no event is received
+                _ = tx.closed() => {
+                    cancel.cancel();
+                    query.await
+                }
+            };
+            if let Err(e) = res {
+                tracing::error!("Impossible to stream events of {}: {e}", relay.url);
+            }
+        });
+        futures_util::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|event| (event, rx))
+        })
+    }
+
+    /// Check if an [`Event`] is present on the relay
+    ///
+    /// Useful to verify the propagation of an event published to another relay.
+    pub async fn confirm_event_present(
+        &self,
+        id: EventId,
+        timeout: Option<Duration>,
+    ) -> Result<bool, Error> {
+        Ok(self.get_event_by_id(id, timeout).await?.is_some())
+    }
+
+    /// Get [`Event`] by [`EventId`]
+    ///
+    /// Return as soon as the event is received, without waiting for `EOSE`.
+    /// Return `None` if the relay doesn't have it.
+    pub async fn get_event_by_id(
+        &self,
+        id: EventId,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Event>, Error> {
+        if !self.read_allowed()? {
+            return Ok(None);
+        }
+
+        let subscription_id = SubscriptionId::generate();
+        let filter = Filter::new().id(id.to_hex()).limit(1);
+
+        let mut notifications = self.notification_sender.subscribe();
+        self.send_msg(
+            ClientMessage::new_req(subscription_id.clone(), vec![filter]),
+            None,
+        )
+        .await?;
+
+        let res = time::timeout(timeout, async {
+            while let Some(notification) = recv_notification(&mut notifications).await {
+                if let RelayPoolNotification::Message(url, msg) = notification {
+                    if url != self.url {
+                        continue;
+                    }
+                    match msg {
+                        RelayMessage::Event {
+                            subscription_id: sub_id,
+                            event,
+                        } if sub_id == subscription_id && event.id == id => {
+                            return Ok(Some(*event));
+                        }
+                        RelayMessage::EndOfStoredEvents(sub_id) if sub_id == subscription_id => {
+                            return Ok(None);
                         }
-                    };
+                        RelayMessage::Closed {
+                            subscription_id: sub_id,
+                            message,
+                        } if sub_id == subscription_id => {
+                            return Err(Error::SubscriptionClosed { reason: message });
+                        }
+                        _ => (),
+                    }
                 }
             }
+            Err(Error::LoopTerminated)
         })
         .await
-        .ok_or(Error::Timeout)?;
+        .ok_or(Error::Timeout);
 
-        if let FilterOptions::WaitDurationAfterEOSE(duration) = opts {
-            time::timeout(Some(duration), async {
-                while let Ok(notification) = notifications.recv().await {
-                    if let RelayPoolNotification::Message(
-                        _,
-                        RelayMessage::Event {
-                            subscription_id,
-                            event,
-                        },
-                    ) = notification
-                    {
-                        if subscription_id.eq(&id) {
-                            callback(*event).await;
-                        }
-                    }
+        // Unsubscribe
+        self.send_msg(ClientMessage::close(subscription_id), None)
+            .await?;
+
+        res?
+    }
+
+    /// Check if the writes with [`Keys`] are effective on this relay
+    ///
+    /// Publish a tiny test event (expiring after 1 minute) and query it back.
+    /// The result is cached for the public key of the [`Keys`].
+    pub async fn can_write(&self, keys: &Keys, timeout: Option<Duration>) -> Result<bool, Error> {
+        let public_key: XOnlyPublicKey = keys.public_key();
+
+        if let Some(can_write) = self.write_checks.lock().await.get(&public_key) {
+            return Ok(*can_write);
+        }
+
+        let event: Event = EventBuilder::new(
+            Kind::ApplicationSpecificData,
+            "",
+            &[
+                Tag::Identifier(String::from("nostr-sdk-write-check")),
+                Tag::Expiration(Timestamp::now() + Duration::from_secs(60)),
+            ],
+        )
+        .to_event(keys)?;
+        let id: EventId = event.id;
+
+        let can_write: bool = match self
+            .send_event(event, RelaySendOptions::new().timeout(timeout))
+            .await
+        {
+            Ok(_) => self.confirm_event_present(id, timeout).await?,
+            Err(Error::EventNotPublished(_)) => false,
+            Err(e) => return Err(e),
+        };
+
+        let mut write_checks = self.write_checks.lock().await;
+        write_checks.insert(public_key, can_write);
+
+        Ok(can_write)
+    }
+
+    /// Probe which [`Kind`]s are accepted by the relay
+    ///
+    /// **This publishes a real event** for each kind not cached yet: an empty event signed with `keys`,
+    /// with a NIP-40 expiration of 1 minute. Relays that ignore NIP-40 keep it, and other clients may see it,
+    /// so use throwaway [`Keys`] when possible.
+    ///
+    /// Replaceable, parameterized replaceable and deletion kinds would overwrite or delete events of `keys`:
+    /// the probe fails with [`Error::KindNotProbeable`], before publishing anything, if any of them is requested.
+    ///
+    /// Kinds that timed out are reported as not accepted, but aren't cached.
+    /// The other results are cached.
+    pub async fn probe_accepted_kinds(
+        &self,
+        kinds: Vec<Kind>,
+        keys: &Keys,
+        timeout: Option<Duration>,
+    ) -> Result<HashMap<Kind, bool>, Error> {
+        if let Some(kind) = kinds.iter().find(|kind| !is_probeable(kind)) {
+            return Err(Error::KindNotProbeable(*kind));
+        }
+
+        let mut results: HashMap<Kind, bool> = HashMap::new();
+
+        for kind in kinds.into_iter() {
+            if let Some(accepted) = self.accepted_kinds.lock().await.get(&kind) {
+                results.insert(kind, *accepted);
+                continue;
+            }
+
+            let event: Event = EventBuilder::new(
+                kind,
+                "",
+                &[Tag::Expiration(Timestamp::now() + Duration::from_secs(60))],
+            )
+            .to_event(keys)?;
+
+            let accepted: bool = match self
+                .send_event(event, RelaySendOptions::new().timeout(timeout))
+                .await
+            {
+                Ok(_) => true,
+                Err(Error::EventNotPublished(_)) => false,
+                Err(Error::Timeout) => {
+                    results.insert(kind, false);
+                    continue;
                 }
-            })
-            .await;
+                Err(e) => return Err(e),
+            };
+
+            let mut accepted_kinds = self.accepted_kinds.lock().await;
+            accepted_kinds.insert(kind, accepted);
+            results.insert(kind, accepted);
         }
 
-        Ok(())
+        Ok(results)
     }
 
-    /// Get events of filters with custom callback
-    pub async fn get_events_of_with_callback<F>(
+    /// Count events of filters (NIP-45)
+    ///
+    /// Fail with [`Error::NotSupported`] if the fetched [`RelayInformationDocument`] doesn't list NIP-45.
+    /// If the support is unknown, the `COUNT` is sent anyway: a relay without NIP-45 replies with `CLOSED` or times out.
+    pub async fn count_events_of(
         &self,
         filters: Vec<Filter>,
         timeout: Option<Duration>,
-        opts: FilterOptions,
-        callback: impl Fn(Event) -> F,
-    ) -> Result<(), Error>
-    where
-        F: Future<Output = ()>,
-    {
-        if !self.opts.read() {
-            return Err(Error::ReadDisabled);
+    ) -> Result<usize, Error> {
+        if !self.read_allowed()? {
+            return Ok(0);
         }
 
-        let id = SubscriptionId::generate();
-
-        self.send_msg(ClientMessage::new_req(id.clone(), filters), None)
-            .await?;
+        #[cfg(feature = "nip11")]
+        self.check_nip_support(45).await?;
 
-        self.handle_events_of(id.clone(), timeout, opts, callback)
+        let id = SubscriptionId::generate();
+        let mut notifications = self.notification_sender.subscribe();
+        self.send_msg(ClientMessage::new_count(id.clone(), filters), None)
             .await?;
 
-        // Unsubscribe
-        self.send_msg(ClientMessage::close(id), None).await?;
-
-        Ok(())
+        time::timeout(timeout, async {
+            while let Some(notification) = recv_notification(&mut notifications).await {
+                if let RelayPoolNotification::Message(url, msg) = notification {
+                    if url != self.url {
+                        continue;
+                    }
+                    match msg {
+                        RelayMessage::Count {
+                            subscription_id,
+                            count,
+                        } if subscription_id == id => return Ok(count),
+                        RelayMessage::Closed {
+                            subscription_id,
+                            message,
+                        } if subscription_id == id => {
+                            return Err(Error::SubscriptionClosed { reason: message });
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            Err(Error::LoopTerminated)
+        })
+        .await
+        .ok_or(Error::Timeout)?
     }
 
-    /// Get events of filters
-    pub async fn get_events_of(
+    /// Search events (NIP-50)
+    ///
+    /// The query is added to every filter, keeping their other constraints (the events must match both).
+    /// If a filter has already a `search`, the query is appended to it. Without filters, search in all the events.
+    ///
+    /// Fail with [`Error::NotSupported`] if the fetched [`RelayInformationDocument`] doesn't list NIP-50.
+    /// If the support is unknown, the `REQ` is sent anyway: a relay without NIP-50 ignores the `search` or replies with `CLOSED`.
+    pub async fn search_events(
         &self,
+        query: String,
         filters: Vec<Filter>,
         timeout: Option<Duration>,
-        opts: FilterOptions,
     ) -> Result<Vec<Event>, Error> {
-        let events: Mutex<Vec<Event>> = Mutex::new(Vec::new());
-        self.get_events_of_with_callback(filters, timeout, opts, |event| async {
-            let mut events = events.lock().await;
-            events.push(event);
-        })
-        .await?;
-        Ok(events.into_inner())
+        let query: &str = query.trim();
+        if query.is_empty() {
+            return Err(Error::SearchQueryEmpty);
+        }
+
+        #[cfg(feature = "nip11")]
+        self.check_nip_support(50).await?;
+
+        let filters: Vec<Filter> = if filters.is_empty() {
+            vec![Filter::new().search(query)]
+        } else {
+            filters
+                .into_iter()
+                .map(|filter| match filter.search.clone() {
+                    Some(search) if !search.trim().is_empty() => {
+                        filter.search(format!("{} {query}", search.trim()))
+                    }
+                    _ => filter.search(query),
+                })
+                .collect()
+        };
+
+        self.get_events_of(filters, timeout, FilterOptions::ExitOnEOSE)
+            .await
     }
 
     /// Request events of filter. All events will be sent to notification listener,
     /// until the EOSE "end of stored events" message is received from the relay.
+    ///
+    /// Return a [`CancelHandle`] to stop the request early (the subscription is closed).
     pub fn req_events_of(
         &self,
         filters: Vec<Filter>,
         timeout: Option<Duration>,
         opts: FilterOptions,
-    ) {
-        if !self.opts.read() {
-            tracing::error!("{}", Error::ReadDisabled);
+    ) -> CancelHandle {
+        let cancel = CancelHandle::new();
+
+        match self.read_allowed() {
+            Ok(true) => (),
+            Ok(false) => return cancel,
+            Err(e) => tracing::error!("{e}"),
         }
 
         let relay = self.clone();
+        let handle = cancel.clone();
         thread::spawn(async move {
+            let _slot: QuerySlot = relay.acquire_query_slot().await;
+
             let id = SubscriptionId::generate();
 
             // Subscribe
@@ -1256,7 +5017,14 @@ impl Relay {
             };
 
             if let Err(e) = relay
-                .handle_events_of(id.clone(), timeout, opts, |_| async {})
+                .handle_events_of_until_cancelled(
+                    id.clone(),
+                    &[],
+                    timeout,
+                    opts,
+                    |_| async {},
+                    Some(&handle),
+                )
                 .await
             {
                 tracing::error!("{e}");
@@ -1271,5 +5039,824 @@ impl Relay {
                 );
             }
         });
+
+        cancel
+    }
+}
+
+/// Receive the next [`RelayPoolNotification`], skipping the lagged ones
+///
+/// Return `None` when the channel is closed.
+pub(crate) async fn recv_notification(
+    notifications: &mut broadcast::Receiver<RelayPoolNotification>,
+) -> Option<RelayPoolNotification> {
+    loop {
+        match notifications.recv().await {
+            Ok(notification) => return Some(notification),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                tracing::warn!("Notification receiver lagged: {skipped} notifications skipped")
+            }
+            Err(broadcast::error::RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Check if publishing a probe event of [`Kind`] leaves the other events of the author untouched
+fn is_probeable(kind: &Kind) -> bool {
+    !matches!(kind.as_u64(), 0 | 3 | 5 | 10_000..=19_999 | 30_000..=39_999)
+}
+
+/// Estimated size of the [`LiveFeed`]s, in bytes
+fn live_feeds_size(live_feeds: &HashMap<SubscriptionId, LiveFeed>) -> usize {
+    live_feeds
+        .keys()
+        .map(|id| id.to_string().len() + std::mem::size_of::<LiveFeed>())
+        .sum()
+}
+
+/// Key of the query cache: hash of the filters and of the [`FilterOptions`]
+fn query_cache_key(filters: &[Filter], opts: FilterOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for filter in filters.iter() {
+        filter.as_json().hash(&mut hasher);
+    }
+    format!("{opts:?}").hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Split the largest list between `authors` and `ids` of the filter in chunks of at most `threshold` items
+///
+/// The other list is kept whole in every chunk, to not send `authors * ids` `REQ`.
+fn split_filter(filter: &Filter, threshold: usize) -> Vec<Filter> {
+    let split_authors: bool = filter.authors.len() >= filter.ids.len();
+    let items: &[String] = if split_authors {
+        &filter.authors
+    } else {
+        &filter.ids
+    };
+
+    if items.len() <= threshold {
+        return vec![filter.clone()];
+    }
+
+    items
+        .chunks(threshold)
+        .map(|chunk| {
+            let mut f: Filter = filter.clone();
+            if split_authors {
+                f.authors = chunk.to_vec();
+            } else {
+                f.ids = chunk.to_vec();
+            }
+            f
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::relay::transport::memory::{MemoryRelay, MemorySocket};
+
+    fn text_note(keys: &Keys, content: &str) -> Event {
+        EventBuilder::new_text_note(content, &[])
+            .to_event(keys)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_handle_events_of_dedup() {
+        let opts = RelayOptions::default().dedup_events(true);
+        let mut mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+
+        let event = text_note(&Keys::generate(), "test");
+        let events = vec![event.clone(), event.clone()];
+        let (res, _) = tokio::join!(
+            relay.get_events_of(
+                vec![Filter::new().kind(Kind::TextNote)],
+                Some(Duration::from_secs(5)),
+                FilterOptions::ExitOnEOSE
+            ),
+            mem.socket.answer_req(&events)
+        );
+        assert_eq!(res.unwrap(), vec![event]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_event_partial_publish_order() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+
+        let keys = Keys::generate();
+        let events: Vec<Event> = (0..5)
+            .map(|i| text_note(&keys, &format!("reply {i}")))
+            .collect();
+        let ids: Vec<EventId> = events.iter().map(|e| e.id).collect();
+
+        let batch = relay.batch_event(
+            events,
+            RelaySendOptions::new().timeout(Some(Duration::from_secs(5))),
+        );
+        let feed = async {
+            for id in ids.iter() {
+                match mem.socket.recv().await {
+                    ClientMessage::Event(event) => assert_eq!(event.id, *id),
+                    msg => panic!("unexpected message: {msg:?}"),
+                }
+            }
+            // OKs received in reverse order, one rejected
+            for (i, id) in ids.iter().enumerate().rev() {
+                mem.socket.send(RelayMessage::new_ok(*id, i != 2, ""));
+            }
+        };
+
+        let (res, _) = tokio::join!(batch, feed);
+        match res {
+            Err(Error::PartialPublish {
+                published,
+                not_published,
+            }) => {
+                assert_eq!(published, vec![ids[0], ids[1], ids[3], ids[4]]);
+                assert!(not_published.contains_key(&ids[2]));
+            }
+            res => panic!("unexpected result: {res:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_unchanged_filters() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+
+        let internal_id = InternalSubscriptionId::Custom(String::from("test"));
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+
+        relay
+            .subscribe_with_internal_id(internal_id.clone(), filters.clone(), None)
+            .await
+            .unwrap();
+        assert!(matches!(mem.socket.recv().await, ClientMessage::Req { .. }));
+
+        // Same filters: no REQ sent
+        relay
+            .subscribe_with_internal_id(internal_id.clone(), filters.clone(), None)
+            .await
+            .unwrap();
+        assert!(mem.flush().await.is_empty());
+
+        // Forced
+        relay
+            .subscribe_with_internal_id_opts(
+                internal_id,
+                filters,
+                None,
+                SubscribeOptions::new().force(true),
+            )
+            .await
+            .unwrap();
+        assert!(matches!(mem.socket.recv().await, ClientMessage::Req { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_id_collision() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+
+        let id = SubscriptionId::new("custom");
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+
+        relay
+            .subscribe_with_internal_id_opts(
+                InternalSubscriptionId::Custom(String::from("a")),
+                filters.clone(),
+                None,
+                SubscribeOptions::new().id(id.clone()),
+            )
+            .await
+            .unwrap();
+        match mem.socket.recv().await {
+            ClientMessage::Req {
+                subscription_id, ..
+            } => assert_eq!(subscription_id, id),
+            msg => panic!("unexpected message: {msg:?}"),
+        }
+
+        let res = relay
+            .subscribe_with_internal_id_opts(
+                InternalSubscriptionId::Custom(String::from("b")),
+                filters,
+                None,
+                SubscribeOptions::new().id(id.clone()),
+            )
+            .await;
+        assert!(matches!(res, Err(Error::SubscriptionIdCollision(collision)) if collision == id));
+        assert_eq!(relay.subscription_count().await, 1);
+        assert!(mem.flush().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_all() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+
+        for id in ["a", "b"] {
+            relay
+                .subscribe_with_internal_id(
+                    InternalSubscriptionId::Custom(id.to_string()),
+                    vec![Filter::new().kind(Kind::TextNote)],
+                    None,
+                )
+                .await
+                .unwrap();
+            mem.socket.recv().await;
+        }
+
+        relay.unsubscribe_all(None).await.unwrap();
+        for _ in 0..2 {
+            assert!(matches!(mem.socket.recv().await, ClientMessage::Close(..)));
+        }
+        assert_eq!(relay.subscription_count().await, 0);
+
+        // Nothing to resubscribe
+        relay.resubscribe_all(None).await.unwrap();
+        assert!(mem.flush().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_query_cache_trimmed_over_max_memory() {
+        let opts = RelayOptions::default()
+            .query_cache_ttl(Some(Duration::from_secs(60)))
+            .max_memory(Some(1));
+        let mut mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+
+        let events = vec![text_note(&Keys::generate(), "test")];
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+
+        // The cached query is evicted right away: both queries are sent to the relay
+        for _ in 0..2 {
+            let (res, _) = tokio::join!(
+                relay.get_events_of(
+                    filters.clone(),
+                    Some(Duration::from_secs(5)),
+                    FilterOptions::ExitOnEOSE
+                ),
+                mem.socket.answer_req(&events)
+            );
+            assert_eq!(res.unwrap().len(), 1);
+        }
+
+        let stats = relay.stats();
+        assert_eq!(stats.cache_hits(), 0);
+        assert_eq!(stats.cache_misses(), 2);
+        assert_eq!(relay.estimated_memory_usage().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_probe_accepted_kinds() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+        let keys = Keys::generate();
+        let timeout = Some(Duration::from_secs(5));
+
+        // Replaceable, parameterized replaceable and deletion kinds are refused before publishing
+        for kind in [
+            Kind::Metadata,
+            Kind::ContactList,
+            Kind::EventDeletion,
+            Kind::Replaceable(10_002),
+            Kind::LongFormTextNote,
+        ] {
+            let res = relay
+                .probe_accepted_kinds(vec![Kind::TextNote, kind], &keys, timeout)
+                .await;
+            assert!(matches!(res, Err(Error::KindNotProbeable(k)) if k == kind));
+        }
+        assert!(mem.flush().await.is_empty());
+
+        // Text notes accepted, reactions rejected
+        let answer = async {
+            for _ in 0..2 {
+                match mem.socket.recv().await {
+                    ClientMessage::Event(event) => {
+                        let accepted: bool = event.kind == Kind::TextNote;
+                        mem.socket
+                            .send(RelayMessage::new_ok(event.id, accepted, ""));
+                    }
+                    msg => panic!("unexpected message: {msg:?}"),
+                }
+            }
+        };
+        let (res, _) = tokio::join!(
+            relay.probe_accepted_kinds(vec![Kind::TextNote, Kind::Reaction], &keys, timeout),
+            answer
+        );
+        let results = res.unwrap();
+        assert_eq!(results.get(&Kind::TextNote), Some(&true));
+        assert_eq!(results.get(&Kind::Reaction), Some(&false));
+
+        // Cached: nothing published
+        let results = relay
+            .probe_accepted_kinds(vec![Kind::TextNote, Kind::Reaction], &keys, timeout)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(mem.flush().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_send_event_retries() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+        let keys = Keys::generate();
+        let opts = RelaySendOptions::new()
+            .timeout(Some(Duration::from_secs(5)))
+            .retries(2)
+            .retry_delay(Duration::from_millis(10));
+
+        // Transient rejection: sent again
+        let event = text_note(&keys, "retry");
+        let answer = async {
+            let first = mem
+                .socket
+                .answer_event(false, "rate-limited: slow down")
+                .await;
+            let second = mem.socket.answer_event(true, "").await;
+            (first.id, second.id)
+        };
+        let (res, (first, second)) = tokio::join!(relay.send_event(event.clone(), opts), answer);
+        assert_eq!(res.unwrap(), event.id);
+        assert_eq!(first, event.id);
+        assert_eq!(second, event.id);
+
+        // Permanent rejection: not sent again
+        let event = text_note(&keys, "invalid");
+        let (res, _) = tokio::join!(
+            relay.send_event(event, opts),
+            mem.socket.answer_event(false, "invalid: bad event")
+        );
+        assert!(matches!(res, Err(Error::EventNotPublished(..))));
+        assert!(mem.flush().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_events_of_cancellable() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+        let mut notifications = mem.pool.notifications();
+        let event = text_note(&Keys::generate(), "test");
+        let cancel = CancelHandle::new();
+
+        // The relay sends an event, but never the EOSE
+        let feed = async {
+            let id = match mem.socket.recv().await {
+                ClientMessage::Req {
+                    subscription_id, ..
+                } => subscription_id,
+                msg => panic!("unexpected message: {msg:?}"),
+            };
+            mem.socket
+                .send(RelayMessage::new_event(id.clone(), event.clone()));
+            // Once broadcasted, the event is handled by the query at its next poll
+            while !matches!(
+                notifications.recv().await.unwrap(),
+                RelayPoolNotification::Message(_, RelayMessage::Event { .. })
+            ) {}
+            tokio::task::yield_now().await;
+            cancel.cancel();
+            id
+        };
+        let (res, id) = tokio::join!(
+            relay.get_events_of_cancellable(
+                vec![Filter::new().kind(Kind::TextNote)],
+                Some(Duration::from_secs(5)),
+                FilterOptions::ExitOnEOSE,
+                cancel.clone()
+            ),
+            feed
+        );
+        assert_eq!(res.unwrap(), vec![event]);
+        assert!(cancel.is_cancelled());
+
+        // Subscription closed
+        assert!(matches!(mem.socket.recv().await, ClientMessage::Close(closed) if closed == id));
+    }
+
+    #[tokio::test]
+    async fn test_query_cache() {
+        let opts = RelayOptions::default().query_cache_ttl(Some(Duration::from_secs(60)));
+        let mut mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+        let events = vec![text_note(&Keys::generate(), "test")];
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+        let timeout = Some(Duration::from_secs(5));
+
+        let (res, id) = tokio::join!(
+            relay.get_events_of(filters.clone(), timeout, FilterOptions::ExitOnEOSE),
+            mem.socket.answer_req(&events)
+        );
+        assert_eq!(res.unwrap(), events);
+        assert!(matches!(mem.socket.recv().await, ClientMessage::Close(closed) if closed == id));
+
+        // Served from the cache: nothing sent
+        let res = relay
+            .get_events_of(filters.clone(), timeout, FilterOptions::ExitOnEOSE)
+            .await;
+        assert_eq!(res.unwrap(), events);
+        assert!(mem.flush().await.is_empty());
+        let stats = relay.stats();
+        assert_eq!(stats.cache_hits(), 1);
+        assert_eq!(stats.cache_misses(), 1);
+
+        // Cleared: REQ sent again
+        relay.clear_query_cache().await;
+        let (res, _) = tokio::join!(
+            relay.get_events_of(filters, timeout, FilterOptions::ExitOnEOSE),
+            mem.socket.answer_req(&events)
+        );
+        assert_eq!(res.unwrap(), events);
+        assert_eq!(stats.cache_misses(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit() {
+        let opts = RelayOptions::default().rate_limit(1.0, 2);
+        let mut mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+        let keys = Keys::generate();
+
+        assert_eq!(relay.available_tokens().await.map(f64::round), Some(2.0));
+
+        // Burst
+        for content in ["a", "b"] {
+            let msg = ClientMessage::new_event(text_note(&keys, content));
+            relay.send_msg(msg, None).await.unwrap();
+            assert!(matches!(mem.socket.recv().await, ClientMessage::Event(..)));
+        }
+        assert!(relay.available_tokens().await.unwrap() < 1.0);
+
+        // Bucket empty: not sent within the wait timeout
+        let msg = ClientMessage::new_event(text_note(&keys, "c"));
+        let res = relay.send_msg(msg, Some(Duration::from_millis(100))).await;
+        assert!(matches!(res, Err(Error::RateLimited)));
+
+        // Other messages aren't limited (the marker of the flush is a `CLOSE`)
+        assert!(mem.flush().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pause_resume_subscription() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+        let req_id = |msg: ClientMessage| match msg {
+            ClientMessage::Req {
+                subscription_id, ..
+            } => subscription_id,
+            msg => panic!("unexpected message: {msg:?}"),
+        };
+
+        let mut ids = Vec::new();
+        for id in ["a", "b"] {
+            relay
+                .subscribe_with_internal_id(
+                    InternalSubscriptionId::Custom(id.to_string()),
+                    filters.clone(),
+                    None,
+                )
+                .await
+                .unwrap();
+            ids.push(req_id(mem.socket.recv().await));
+        }
+
+        let paused = InternalSubscriptionId::Custom(String::from("a"));
+        relay.pause_subscription(paused.clone()).await.unwrap();
+        assert!(matches!(mem.socket.recv().await, ClientMessage::Close(id) if id == ids[0]));
+
+        // Already paused: nothing sent
+        relay.pause_subscription(paused.clone()).await.unwrap();
+        assert!(mem.flush().await.is_empty());
+
+        // Paused subscription skipped, but kept
+        relay.resubscribe_all(None).await.unwrap();
+        let msgs = mem.flush().await;
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(req_id(msgs[0].clone()), ids[1]);
+        assert_eq!(relay.subscription_count().await, 2);
+
+        // Resumed with the same ID and filters
+        relay.resume_subscription(paused).await.unwrap();
+        match mem.socket.recv().await {
+            ClientMessage::Req {
+                subscription_id,
+                filters: req_filters,
+            } => {
+                assert_eq!(subscription_id, ids[0]);
+                assert_eq!(req_filters, filters);
+            }
+            msg => panic!("unexpected message: {msg:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extra_sockets_closed_on_disconnect() {
+        let opts = RelayOptions::default().connections(2);
+        let mut mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+        let mut notifications = mem.pool.notifications();
+        let mut extra = mem.sockets.recv().await.unwrap();
+
+        // Messages of the additional socket are handled while connected
+        extra.send(RelayMessage::new_notice("before"));
+        loop {
+            if let RelayPoolNotification::Message(_, RelayMessage::Notice { message }) =
+                notifications.recv().await.unwrap()
+            {
+                assert_eq!(message, "before");
+                break;
+            }
+        }
+
+        // Reader and writer stopped with the main connection
+        relay.reconnect().await.unwrap();
+        time::timeout(Some(Duration::from_secs(5)), extra.outgoing.closed())
+            .await
+            .unwrap();
+        assert!(extra.incoming.recv().await.is_none());
+        assert!(extra
+            .outgoing
+            .send(WsMessage::Text(RelayMessage::new_notice("after").as_json()))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_only_relay_read_closed() {
+        let opts = RelayOptions::new(false, true).disconnect_on_read_close(false);
+        let mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+        let keys = Keys::generate();
+
+        // Close the read side
+        let MemorySocket {
+            mut incoming,
+            outgoing,
+        } = mem.socket;
+        drop(outgoing);
+        time::timeout(Some(Duration::from_secs(5)), async {
+            while !relay.is_read_closed() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(relay.is_connected().await);
+
+        // Still publishing
+        let event = text_note(&keys, "written");
+        let opts = RelaySendOptions::new().confirmation(SendConfirmation::Written);
+        assert_eq!(
+            relay.send_event(event.clone(), opts).await.unwrap(),
+            event.id
+        );
+        let msg = ClientMessage::from_json(incoming.recv().await.unwrap().into_text().unwrap());
+        assert!(matches!(msg, Ok(ClientMessage::Event(sent)) if sent.id == event.id));
+
+        // The `OK` can't be received
+        let opts = RelaySendOptions::new().timeout(Some(Duration::from_secs(5)));
+        let res = relay
+            .send_event(text_note(&keys, "acknowledged"), opts)
+            .await;
+        assert!(matches!(res, Err(Error::ReadClosed)));
+    }
+
+    #[tokio::test]
+    async fn test_write_only_relay_with_ping_disconnects_on_read_close() {
+        let opts = RelayOptions::new(false, true)
+            .disconnect_on_read_close(false)
+            .ping_interval(Some(Duration::from_secs(60)));
+        let mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+        let mut notifications = mem.pool.notifications();
+
+        drop(mem.socket.outgoing);
+        loop {
+            if let RelayPoolNotification::RelayStatus {
+                status: RelayStatus::Disconnected,
+                ..
+            } = notifications.recv().await.unwrap()
+            {
+                break;
+            }
+        }
+        assert!(!relay.is_read_closed());
+    }
+
+    #[cfg(feature = "nip11")]
+    #[tokio::test]
+    async fn test_count_events_of_nip_support() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+        let filters = vec![Filter::new().kind(Kind::TextNote)];
+        let timeout = Some(Duration::from_secs(5));
+
+        // Document not fetched: COUNT sent anyway
+        assert!(!relay.is_document_fetched());
+        let count = async {
+            match mem.socket.recv().await {
+                ClientMessage::Count {
+                    subscription_id, ..
+                } => mem
+                    .socket
+                    .send(RelayMessage::new_count(subscription_id, 42)),
+                msg => panic!("unexpected message: {msg:?}"),
+            }
+        };
+        let (res, _) = tokio::join!(relay.count_events_of(filters.clone(), timeout), count);
+        assert_eq!(res.unwrap(), 42);
+
+        // NIP-45 missing from the fetched document
+        let mut document = RelayInformationDocument::new();
+        document.supported_nips = Some(vec![1, 11]);
+        relay.set_document(document).await;
+        let res = relay.count_events_of(filters, timeout).await;
+        assert!(matches!(res, Err(Error::NotSupported(45))));
+        assert!(mem.flush().await.is_empty());
+    }
+
+    #[cfg(feature = "nip11")]
+    #[tokio::test]
+    async fn test_search_events_nip_support() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+        let events = vec![text_note(&Keys::generate(), "nostr")];
+        let timeout = Some(Duration::from_secs(5));
+
+        // Document not fetched: REQ sent anyway
+        assert!(!relay.is_document_fetched());
+        let (res, _) = tokio::join!(
+            relay.search_events(String::from("nostr"), Vec::new(), timeout),
+            mem.socket.answer_req(&events)
+        );
+        assert_eq!(res.unwrap(), events);
+
+        // NIP-50 missing from the fetched document
+        let mut document = RelayInformationDocument::new();
+        document.supported_nips = Some(vec![1, 11]);
+        relay.set_document(document).await;
+        let res = relay
+            .search_events(String::from("nostr"), Vec::new(), timeout)
+            .await;
+        assert!(matches!(res, Err(Error::NotSupported(50))));
+    }
+
+    #[tokio::test]
+    async fn test_enforce_filter_match_after_eose() {
+        let opts = RelayOptions::default().enforce_filter_match(true);
+        let mut mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+        let keys = Keys::generate();
+        let stored = text_note(&keys, "stored");
+        let live = text_note(&keys, "live");
+        let reaction = EventBuilder::new_reaction(stored.id, keys.public_key(), "+")
+            .to_event(&keys)
+            .unwrap();
+
+        let feed = async {
+            let id = mem.socket.answer_req(std::slice::from_ref(&stored)).await;
+            // Not matching: dropped, and not counted in `max_events`
+            mem.socket
+                .send(RelayMessage::new_event(id.clone(), reaction.clone()));
+            mem.socket.send(RelayMessage::new_event(id, live.clone()));
+        };
+        let (res, _) = tokio::join!(
+            relay.get_events_of(
+                vec![Filter::new().kind(Kind::TextNote)],
+                Some(Duration::from_secs(5)),
+                FilterOptions::WaitAfterEOSE {
+                    max_events: 1,
+                    max_duration: Duration::from_secs(5),
+                }
+            ),
+            feed
+        );
+        assert_eq!(res.unwrap(), vec![stored, live]);
+        assert_eq!(relay.stats().filter_mismatches(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_stream_events_of_dropped() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+        let event = text_note(&Keys::generate(), "test");
+
+        // No timeout and no EOSE: the subscription stays open until the stream is dropped
+        let mut stream = Box::pin(relay.stream_events_of(
+            vec![Filter::new().kind(Kind::TextNote)],
+            None,
+            FilterOptions::ExitOnEOSE,
+        ));
+        let id = match mem.socket.recv().await {
+            ClientMessage::Req {
+                subscription_id, ..
+            } => subscription_id,
+            msg => panic!("unexpected message: {msg:?}"),
+        };
+        mem.socket
+            .send(RelayMessage::new_event(id.clone(), event.clone()));
+        assert_eq!(stream.next().await, Some(event));
+
+        drop(stream);
+        let msg = time::timeout(Some(Duration::from_secs(5)), mem.socket.recv())
+            .await
+            .unwrap();
+        assert!(matches!(msg, ClientMessage::Close(closed) if closed == id));
+    }
+
+    #[tokio::test]
+    async fn test_batch_event_disconnected() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        let relay = mem.relay.clone();
+        let keys = Keys::generate();
+        let events = vec![text_note(&keys, "first"), text_note(&keys, "second")];
+
+        let opts = RelaySendOptions::new()
+            .batch_size(1)
+            .timeout(Some(Duration::from_secs(60)));
+        let batch = relay.batch_event_with_progress(events, opts, |published, _| {
+            assert_eq!(published, 1);
+        });
+        let relay_side = async {
+            mem.socket.answer_event(true, "").await;
+            // Disconnected while the second chunk waits for its `OK`
+            mem.socket.recv().await;
+            relay.reconnect().await.unwrap();
+        };
+        let (res, _) = time::timeout(Some(Duration::from_secs(5)), async {
+            tokio::join!(batch, relay_side)
+        })
+        .await
+        .unwrap();
+        assert!(matches!(
+            res,
+            Err(Error::Disconnected(DisconnectReason::UserRequested))
+        ));
+    }
+
+    #[test]
+    fn test_split_filter() {
+        let filter = Filter::new()
+            .authors(vec!["a1", "a2", "a3"])
+            .ids(vec!["i1", "i2", "i3", "i4", "i5"]);
+
+        // Only the largest list is split
+        let chunks = split_filter(&filter, 2);
+        assert_eq!(chunks.len(), 3);
+        for chunk in chunks.iter() {
+            assert_eq!(chunk.authors, filter.authors);
+        }
+        let ids: Vec<String> = chunks.into_iter().flat_map(|f| f.ids).collect();
+        assert_eq!(ids, filter.ids);
+
+        // Nothing to split
+        assert_eq!(split_filter(&filter, 5), vec![filter]);
+    }
+
+    #[tokio::test]
+    async fn test_split_events_of_deadline() {
+        let opts = RelayOptions::default().split_threshold(Some(1));
+        let mut mem = MemoryRelay::connect(opts).await;
+        let relay = mem.relay.clone();
+        let filter = Filter::new().authors(vec!["a1", "a2"]);
+        let timeout = Duration::from_millis(300);
+
+        // The first `REQ` is answered late and the second one never
+        let started = Instant::now();
+        let (res, _) = tokio::join!(
+            relay.get_events_of(vec![filter], Some(timeout), FilterOptions::ExitOnEOSE),
+            async {
+                thread::sleep(Duration::from_millis(200)).await;
+                mem.socket.answer_req(&[]).await;
+            }
+        );
+        assert!(matches!(res, Err(Error::Timeout)));
+
+        // The second `REQ` only had the time left
+        assert!(started.elapsed() < timeout + Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_active_proxy() {
+        let pool = pool::RelayPool::new(RelayPoolOptions::default());
+        let url = Url::parse("wss://127.0.0.1:1").unwrap();
+        let proxy: SocketAddr = "127.0.0.1:9050".parse().unwrap();
+        pool.add_relay(url.clone(), Some(proxy), RelayOptions::default())
+            .await
+            .unwrap();
+        let relay = pool.relay(url).await.unwrap();
+
+        // Never connected: no proxy used yet
+        assert_eq!(relay.proxy(), Some(proxy));
+        assert_eq!(relay.snapshot().await.proxy, None);
     }
 }
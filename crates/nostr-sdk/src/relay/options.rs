@@ -1,10 +1,59 @@
 // Copyright (c) 2022-2023 Yuki Kishimoto
 // Distributed under the MIT software license
 
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use nostr::secp256k1::rand;
+use nostr::{ClientMessage, Keys, SubscriptionId};
+#[cfg(not(target_arch = "wasm32"))]
+use nostr_sdk_net::{ConnectionConfig, IpPreference, Resolver};
+
+#[cfg(not(target_arch = "wasm32"))]
+use super::transport::Transport;
+
+/// Custom [`ClientMessage`] serializer
+pub type MessageSerializer = Arc<dyn Fn(&ClientMessage) -> String + Send + Sync>;
+
+#[derive(Clone)]
+struct Serializer(MessageSerializer);
+
+impl fmt::Debug for Serializer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Serializer").finish()
+    }
+}
+
+/// Hook called with every raw message received from the relay
+pub type RawMessageHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Clone)]
+struct RawHook(RawMessageHook);
+
+impl fmt::Debug for RawHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RawHook").finish()
+    }
+}
+
+/// Policy applied when the outgoing message queue of a [`Relay`] is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueueOverflowPolicy {
+    /// Wait for free capacity
+    Block,
+    /// Drop the oldest queued message
+    DropOldest,
+    /// Return an error
+    #[default]
+    Error,
+}
+
 /// [`Relay`] options
 #[derive(Debug, Clone)]
 pub struct RelayOptions {
@@ -12,59 +61,1330 @@ pub struct RelayOptions {
     read: Arc<AtomicBool>,
     /// Allow/disallow write actions
     write: Arc<AtomicBool>,
+    /// IP version preference (default: both)
+    #[cfg(not(target_arch = "wasm32"))]
+    ip_version: IpPreference,
+    /// Max estimated memory usage, in bytes (default: none)
+    max_memory: Option<usize>,
+    /// Custom [`ClientMessage`] serializer (default: none)
+    message_serializer: Option<Serializer>,
+    /// Hook called with every raw incoming message (default: none)
+    raw_message_hook: Option<RawHook>,
+    /// Max lifetime of the auto connect loop (default: none)
+    loop_max_lifetime: Option<Duration>,
+    /// Interval between checks of the connection status while connected (default: 20 secs)
+    retry_interval: Duration,
+    /// Interval before the first reconnection attempt (default: 5 secs)
+    min_retry_interval: Duration,
+    /// Max interval between reconnection attempts (default: 5 min)
+    max_retry_interval: Duration,
+    /// Retry interval multiplier applied after each consecutive failure (default: 2.0)
+    retry_multiplier: f64,
+    /// Randomize the retry interval (default: true)
+    retry_jitter: bool,
+    /// Reject live events older than the newest one seen for the subscription by more than this window (default: none)
+    reject_backwards: Option<Duration>,
+    /// Interval between pings (default: none)
+    #[cfg(not(target_arch = "wasm32"))]
+    ping_interval: Option<Duration>,
+    /// Time after which the cached NIP-11 document is considered stale (default: none)
+    #[cfg(feature = "nip11")]
+    document_ttl: Option<Duration>,
+    /// Timeout of the NIP-11 document fetch (default: 10 secs)
+    #[cfg(feature = "nip11")]
+    nip11_timeout: Option<Duration>,
+    /// Timeout used to send events when [`RelaySendOptions::timeout`] is not set (default: none)
+    default_send_timeout: Option<Duration>,
+    /// Timeout of the whole connection attempt, handshake included (default: none)
+    connection_timeout: Option<Duration>,
+    /// Keys used to automatically reply to the `AUTH` challenges (default: none)
+    auth_keys: Option<Keys>,
+    /// Outgoing message queue capacity (default: 1024)
+    queue_capacity: usize,
+    /// Policy applied when the outgoing message queue is full (default: error)
+    queue_overflow_policy: QueueOverflowPolicy,
+    /// Behavior of the read/write actions when disabled (default: error)
+    disabled_policy: DisabledPolicy,
+    /// Keep the messages that failed to be written and send them on the next connection (default: false)
+    store_and_forward: bool,
+    /// Max age of the queued messages: older ones are dropped instead of sent (default: none)
+    max_queued_age: Option<Duration>,
+    /// Deliver each event only once per `get_events_of` call (default: false)
+    dedup_events: bool,
+    /// Verify id and signature of the received events (default: false)
+    verify_events: bool,
+    /// Drop the received events not matching the filters of their subscription (default: false)
+    enforce_filter_match: bool,
+    /// Max size of the received messages, in bytes (default: none)
+    max_message_size: Option<usize>,
+    /// Max number of `authors` or `ids` per filter before splitting it in multiple `REQ` (default: none)
+    split_threshold: Option<usize>,
+    /// Resubscribe all the [`ActiveSubscription`](crate::relay::ActiveSubscription) after reconnection (default: true)
+    auto_resubscribe: bool,
+    /// Disconnect a write-only relay when the read side of the connection closes (default: true)
+    disconnect_on_read_close: bool,
+    /// Time without receiving data, while subscriptions are active, after which the connection is stale (default: none)
+    stale_timeout: Option<Duration>,
+    /// Time without outgoing messages and subscriptions after which the relay is disconnected (default: none)
+    idle_timeout: Option<Duration>,
+    /// Reconnect when the connection is stale (default: false)
+    reconnect_on_stale: bool,
+    /// Stop reading from the socket while the consumers are lagging (default: false)
+    pause_ingestion_on_lag: bool,
+    /// Connect timeout and TLS configuration (default: 60 secs timeout, webpki roots)
+    #[cfg(not(target_arch = "wasm32"))]
+    connection_config: ConnectionConfig,
+    /// Allow plaintext `ws://` connections (default: false)
+    allow_insecure: bool,
+    /// Time to live of the cached `get_events_of` results (default: none, cache disabled)
+    query_cache_ttl: Option<Duration>,
+    /// Max number of cached `get_events_of` results (default: 64)
+    query_cache_size: usize,
+    /// Weights of [`Relay::score`](crate::Relay::score)
+    scoring_weights: ScoringWeights,
+    /// Max number of one-shot queries running at the same time (default: none)
+    max_concurrent_queries: Option<usize>,
+    /// Rate limit of the outgoing `EVENT` messages: events per second and burst size (default: none)
+    rate_limit: Option<(f64, usize)>,
+    /// Number of sockets opened to the relay (default: 1)
+    #[cfg(not(target_arch = "wasm32"))]
+    connections: usize,
+    /// Fallback proxies, tried in order when the connection fails (default: none)
+    #[cfg(not(target_arch = "wasm32"))]
+    proxies: Vec<SocketAddr>,
+    /// Custom WebSocket transport (default: none)
+    #[cfg(not(target_arch = "wasm32"))]
+    transport: Option<Arc<dyn Transport>>,
+    /// Custom DNS resolver (default: none, use the system resolver)
+    #[cfg(not(target_arch = "wasm32"))]
+    resolver: Option<Arc<dyn Resolver>>,
+    /// Capacity of the notification channel, set by the relay pool
+    notification_capacity: Option<usize>,
+}
+
+impl Default for RelayOptions {
+    fn default() -> Self {
+        Self::new(true, true)
+    }
+}
+
+impl RelayOptions {
+    /// New [`RelayOptionsBuilder`]
+    pub fn builder() -> RelayOptionsBuilder {
+        RelayOptionsBuilder::new()
+    }
+
+    /// New [`RelayOptions`]
+    pub fn new(read: bool, write: bool) -> Self {
+        Self {
+            read: Arc::new(AtomicBool::new(read)),
+            write: Arc::new(AtomicBool::new(write)),
+            #[cfg(not(target_arch = "wasm32"))]
+            ip_version: IpPreference::default(),
+            max_memory: None,
+            message_serializer: None,
+            raw_message_hook: None,
+            loop_max_lifetime: None,
+            retry_interval: Duration::from_secs(20),
+            min_retry_interval: Duration::from_secs(5),
+            max_retry_interval: Duration::from_secs(300),
+            retry_multiplier: 2.0,
+            retry_jitter: true,
+            reject_backwards: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            ping_interval: None,
+            #[cfg(feature = "nip11")]
+            document_ttl: None,
+            #[cfg(feature = "nip11")]
+            nip11_timeout: Some(Duration::from_secs(10)),
+            default_send_timeout: None,
+            connection_timeout: None,
+            auth_keys: None,
+            queue_capacity: 1024,
+            queue_overflow_policy: QueueOverflowPolicy::default(),
+            disabled_policy: DisabledPolicy::default(),
+            store_and_forward: false,
+            max_queued_age: None,
+            dedup_events: false,
+            verify_events: false,
+            enforce_filter_match: false,
+            max_message_size: None,
+            split_threshold: None,
+            auto_resubscribe: true,
+            disconnect_on_read_close: true,
+            stale_timeout: None,
+            idle_timeout: None,
+            reconnect_on_stale: false,
+            pause_ingestion_on_lag: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            connection_config: ConnectionConfig::default(),
+            allow_insecure: false,
+            query_cache_ttl: None,
+            query_cache_size: 64,
+            scoring_weights: ScoringWeights::default(),
+            max_concurrent_queries: None,
+            rate_limit: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            connections: 1,
+            #[cfg(not(target_arch = "wasm32"))]
+            proxies: Vec::new(),
+            #[cfg(not(target_arch = "wasm32"))]
+            transport: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            resolver: None,
+            notification_capacity: None,
+        }
+    }
+
+    /// Get read option
+    pub fn read(&self) -> bool {
+        self.read.load(Ordering::SeqCst)
+    }
+
+    /// Set read option
+    pub fn set_read(&self, read: bool) {
+        let _ = self
+            .read
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| Some(read));
+    }
+
+    /// Get write option
+    pub fn write(&self) -> bool {
+        self.write.load(Ordering::SeqCst)
+    }
+
+    /// Set write option
+    pub fn set_write(&self, write: bool) {
+        let _ = self
+            .write
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| Some(write));
+    }
+
+    /// Set IP version preference used when resolving and dialing the relay
+    ///
+    /// Ignored when connecting through a proxy.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn ip_version(self, ip_version: IpPreference) -> Self {
+        Self { ip_version, ..self }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn get_ip_version(&self) -> IpPreference {
+        self.ip_version
+    }
+
+    /// Set max estimated memory usage (in bytes)
+    ///
+    /// Checked when a query is cached: if exceeded, a warning is emitted and the optional caches are trimmed
+    /// (the query cache, least recently used first, then the tracking of [`RelayOptions::reject_backwards`]).
+    /// Subscriptions and queued messages are never dropped.
+    pub fn max_memory(self, max_memory: Option<usize>) -> Self {
+        Self { max_memory, ..self }
+    }
+
+    pub(crate) fn get_max_memory(&self) -> Option<usize> {
+        self.max_memory
+    }
+
+    /// Set custom [`ClientMessage`] serializer
+    ///
+    /// Used in place of [`ClientMessage::as_json`] when sending messages to the relay.
+    pub fn message_serializer(self, serializer: Option<MessageSerializer>) -> Self {
+        Self {
+            message_serializer: serializer.map(Serializer),
+            ..self
+        }
+    }
+
+    /// Set a hook called with every raw incoming message
+    ///
+    /// Called before parsing, so also messages not (yet) supported by [`RelayMessage`](nostr::RelayMessage) are received.
+    pub fn on_raw_message(self, hook: Option<RawMessageHook>) -> Self {
+        Self {
+            raw_message_hook: hook.map(RawHook),
+            ..self
+        }
+    }
+
+    pub(crate) fn raw_message(&self, msg: &str) {
+        if let Some(RawHook(hook)) = &self.raw_message_hook {
+            hook(msg);
+        }
+    }
+
+    pub(crate) fn serialize_msg(&self, msg: &ClientMessage) -> String {
+        match &self.message_serializer {
+            Some(Serializer(serializer)) => serializer(msg),
+            None => msg.as_json(),
+        }
+    }
+
+    /// Set max lifetime of the auto connect loop
+    ///
+    /// When elapsed, the relay is terminated, regardless of its status.
+    pub fn loop_max_lifetime(self, lifetime: Option<Duration>) -> Self {
+        Self {
+            loop_max_lifetime: lifetime,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_loop_max_lifetime(&self) -> Option<Duration> {
+        self.loop_max_lifetime
+    }
+
+    /// Set interval between checks of the connection status while connected
+    ///
+    /// A lost connection is retried at most after this interval. Shorten it for interactive apps,
+    /// lengthen it to save battery. Failed attempts are retried according to
+    /// [`RelayOptions::min_retry_interval`] and [`RelayOptions::max_retry_interval`].
+    pub fn retry_interval(self, interval: Duration) -> Self {
+        Self {
+            retry_interval: interval,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_connected_retry_interval(&self) -> Duration {
+        self.retry_interval
+    }
+
+    /// Set interval before the first reconnection attempt
+    pub fn min_retry_interval(self, interval: Duration) -> Self {
+        Self {
+            min_retry_interval: interval,
+            ..self
+        }
+    }
+
+    /// Set max interval between reconnection attempts
+    pub fn max_retry_interval(self, interval: Duration) -> Self {
+        Self {
+            max_retry_interval: interval,
+            ..self
+        }
+    }
+
+    /// Set retry interval multiplier, applied after each consecutive connection failure
+    ///
+    /// Values lower than `1.0` are treated as `1.0` (constant interval).
+    pub fn retry_multiplier(self, multiplier: f64) -> Self {
+        Self {
+            retry_multiplier: multiplier,
+            ..self
+        }
+    }
+
+    /// Randomize the retry interval (+/- 25%), to avoid reconnecting all the relays at the same time
+    pub fn retry_jitter(self, jitter: bool) -> Self {
+        Self {
+            retry_jitter: jitter,
+            ..self
+        }
+    }
+
+    /// Reject live events older than the newest one already received for the same subscription
+    /// by more than `window`
+    ///
+    /// Stored events (received before `EOSE`) are exempt, since relays don't have to send them in order.
+    pub fn reject_backwards(self, window: Option<Duration>) -> Self {
+        Self {
+            reject_backwards: window,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_reject_backwards(&self) -> Option<Duration> {
+        self.reject_backwards
+    }
+
+    /// Send a ping to the relay every `interval`
+    ///
+    /// If the pong isn't received before the next ping, the relay is disconnected and the auto reconnect loop kicks in.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn ping_interval(self, interval: Option<Duration>) -> Self {
+        Self {
+            ping_interval: interval,
+            ..self
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn get_ping_interval(&self) -> Option<Duration> {
+        self.ping_interval
+    }
+
+    /// Set the time after which the cached NIP-11 document is considered stale
+    ///
+    /// A stale document is refreshed in background on the next access.
+    #[cfg(feature = "nip11")]
+    pub fn document_ttl(self, ttl: Option<Duration>) -> Self {
+        Self {
+            document_ttl: ttl,
+            ..self
+        }
+    }
+
+    #[cfg(feature = "nip11")]
+    pub(crate) fn get_document_ttl(&self) -> Option<Duration> {
+        self.document_ttl
+    }
+
+    /// Set the timeout of the NIP-11 document fetch
+    #[cfg(feature = "nip11")]
+    pub fn nip11_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            nip11_timeout: timeout,
+            ..self
+        }
+    }
+
+    #[cfg(feature = "nip11")]
+    pub(crate) fn get_nip11_timeout(&self) -> Option<Duration> {
+        self.nip11_timeout
+    }
+
+    /// Set the timeout used to send events when [`RelaySendOptions::timeout`] is `None`
+    ///
+    /// Allow to give a shorter deadline to low priority relays (i.e. backups) when publishing to a pool.
+    pub fn default_send_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            default_send_timeout: timeout,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_default_send_timeout(&self) -> Option<Duration> {
+        self.default_send_timeout
+    }
+
+    /// Set the timeout of the whole connection attempt (TCP, proxy and websocket handshake)
+    ///
+    /// On expiry the relay is set as [`RelayStatus::Disconnected`](super::RelayStatus::Disconnected)
+    /// and the attempt counted as a failure. Not to be confused with the per-message `wait` timeout.
+    pub fn connection_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            connection_timeout: timeout,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_connection_timeout(&self) -> Option<Duration> {
+        self.connection_timeout
+    }
+
+    /// Automatically authenticate (NIP-42) with [`Keys`] when an `AUTH` challenge is received
+    pub fn auto_auth(self, keys: Option<Keys>) -> Self {
+        Self {
+            auth_keys: keys,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_auth_keys(&self) -> Option<Keys> {
+        self.auth_keys.clone()
+    }
+
+    /// Set outgoing message queue capacity
+    pub fn queue_capacity(self, capacity: usize) -> Self {
+        Self {
+            queue_capacity: capacity,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_queue_capacity(&self) -> usize {
+        self.queue_capacity
+    }
+
+    /// Set the policy applied when the outgoing message queue is full
+    pub fn queue_overflow_policy(self, policy: QueueOverflowPolicy) -> Self {
+        Self {
+            queue_overflow_policy: policy,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_queue_overflow_policy(&self) -> QueueOverflowPolicy {
+        self.queue_overflow_policy
+    }
+
+    /// Set if the read/write actions disabled by [`RelayOptions::read`]/[`RelayOptions::write`] error or are skipped
+    ///
+    /// With [`DisabledPolicy::Skip`] a pool can treat all its relays uniformly.
+    pub fn disabled_policy(self, policy: DisabledPolicy) -> Self {
+        Self {
+            disabled_policy: policy,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_disabled_policy(&self) -> DisabledPolicy {
+        self.disabled_policy
+    }
+
+    /// Enable store-and-forward mode
+    ///
+    /// The messages queued while disconnected are always sent, in order, on the next successful connection.
+    /// With this mode also the messages that failed to be written because the connection dropped
+    /// are kept and sent again first on the next connection.
+    pub fn store_and_forward(self, enable: bool) -> Self {
+        Self {
+            store_and_forward: enable,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_store_and_forward(&self) -> bool {
+        self.store_and_forward
+    }
+
+    /// Set max age of the queued messages
+    ///
+    /// Messages waiting in the queue for longer are dropped when dequeued, see [`RelayConnectionStats::dropped_stale_messages`](crate::relay::RelayConnectionStats::dropped_stale_messages).
+    pub fn max_queued_age(self, age: Option<Duration>) -> Self {
+        Self {
+            max_queued_age: age,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_max_queued_age(&self) -> Option<Duration> {
+        self.max_queued_age
+    }
+
+    /// Skip the events already delivered in the same `get_events_of` call
+    ///
+    /// Relays can send the same event multiple times for a subscription (i.e. overlapping filters).
+    pub fn dedup_events(self, dedup: bool) -> Self {
+        Self {
+            dedup_events: dedup,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_dedup_events(&self) -> bool {
+        self.dedup_events
+    }
+
+    /// Verify id and signature of the received events, dropping the invalid ones
+    pub fn verify_events(self, verify: bool) -> Self {
+        Self {
+            verify_events: verify,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_verify_events(&self) -> bool {
+        self.verify_events
+    }
+
+    /// Drop the received events not matching the filters of their subscription
+    ///
+    /// Protects from buggy or malicious relays. Checked with [`Filter::match_event`](nostr::Filter::match_event),
+    /// both for live subscriptions and `get_events_of`. Dropped events are counted in
+    /// [`RelayConnectionStats::filter_mismatches`](super::RelayConnectionStats::filter_mismatches).
+    pub fn enforce_filter_match(self, enforce: bool) -> Self {
+        Self {
+            enforce_filter_match: enforce,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_enforce_filter_match(&self) -> bool {
+        self.enforce_filter_match
+    }
+
+    /// Set max size of the received messages (in bytes)
+    ///
+    /// Bigger messages are dropped without being parsed.
+    pub fn max_message_size(self, size: Option<usize>) -> Self {
+        Self {
+            max_message_size: size,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_max_message_size(&self) -> Option<usize> {
+        self.max_message_size
+    }
+
+    /// Set max number of `authors` or `ids` per filter used by [`Relay::get_events_of`](crate::Relay::get_events_of)
+    ///
+    /// Bigger filters are split in chunks, queried sequentially and the results merged.
+    pub fn split_threshold(self, threshold: Option<usize>) -> Self {
+        Self {
+            split_threshold: threshold,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_split_threshold(&self) -> Option<usize> {
+        self.split_threshold
+    }
+
+    /// Resubscribe automatically after reconnection
+    ///
+    /// When disabled the subscriptions are still tracked: use [`Relay::resubscribe_all`](crate::Relay::resubscribe_all) to send them again.
+    pub fn auto_resubscribe(self, auto_resubscribe: bool) -> Self {
+        Self {
+            auto_resubscribe,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_auto_resubscribe(&self) -> bool {
+        self.auto_resubscribe
+    }
+
+    /// Disconnect when the read side of the connection closes
+    ///
+    /// Considered only for write-only relays: when disabled, the connection stays `Connected` and keeps
+    /// publishing after the incoming stream ends. The reconnect loop doesn't run until the connection
+    /// fails on the send side, then it reconnects as usual.
+    ///
+    /// Nothing is received anymore, so the `OK` messages are lost: only [`SendConfirmation::Queued`] and
+    /// [`SendConfirmation::Written`] sends work, the [`SendConfirmation::Acknowledged`] ones fail with
+    /// [`Error::ReadClosed`](crate::relay::Error::ReadClosed) (see [`Relay::is_read_closed`](crate::Relay::is_read_closed)).
+    ///
+    /// Relays with `read` enabled always disconnect, to not lose the subscriptions. So do relays with a
+    /// [`RelayOptions::ping_interval`], since the pongs can't be received.
+    pub fn disconnect_on_read_close(self, disconnect_on_read_close: bool) -> Self {
+        Self {
+            disconnect_on_read_close,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_disconnect_on_read_close(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.ping_interval.is_some() {
+            return true;
+        }
+        self.disconnect_on_read_close || self.read()
+    }
+
+    /// Set the time without receiving data after which a connection with active subscriptions is stale
+    ///
+    /// Checked at every iteration of the auto connect loop.
+    pub fn stale_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            stale_timeout: timeout,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_stale_timeout(&self) -> Option<Duration> {
+        self.stale_timeout
+    }
+
+    /// Set the time without outgoing messages and active subscriptions after which the relay is disconnected
+    ///
+    /// Checked at every iteration of the auto connect loop. The idle relay stays [`RelayStatus::Disconnected`](crate::RelayStatus::Disconnected)
+    /// without reconnecting, until the next message is sent (i.e. [`Relay::send_msg`](crate::Relay::send_msg) or
+    /// [`Relay::subscribe`](crate::Relay::subscribe)): the message is queued and sent after the reconnection.
+    pub fn idle_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            idle_timeout: timeout,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout
+    }
+
+    /// Reconnect when the connection is stale, see [`RelayOptions::stale_timeout`]
+    pub fn reconnect_on_stale(self, reconnect: bool) -> Self {
+        Self {
+            reconnect_on_stale: reconnect,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_reconnect_on_stale(&self) -> bool {
+        self.reconnect_on_stale
+    }
+
+    /// Stop reading from the socket while the pool or the notification channel is full
+    ///
+    /// Let the TCP flow control slow down the relay instead of dropping events.
+    /// Note that a notification receiver that is never read keeps the ingestion paused.
+    pub fn pause_ingestion_on_lag(self, pause: bool) -> Self {
+        Self {
+            pause_ingestion_on_lag: pause,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_pause_ingestion_on_lag(&self) -> bool {
+        self.pause_ingestion_on_lag
+    }
+
+    /// Set connect timeout, TLS verification, custom root certificates and handshake headers
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn connection_config(self, connection_config: ConnectionConfig) -> Self {
+        Self {
+            connection_config,
+            ..self
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn get_connection_config(&self) -> &ConnectionConfig {
+        &self.connection_config
+    }
+
+    /// Set additional HTTP headers of the WebSocket handshake request (i.e. `Authorization`)
+    ///
+    /// Shorthand for [`ConnectionConfig::headers`]. Not available on wasm32, where the browser controls the headers.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.connection_config.headers = headers;
+        self
+    }
+
+    /// Allow plaintext `ws://` connections (i.e. for local development)
+    ///
+    /// When disabled, the connection to `ws://` urls is refused with [`Error::InsecureConnection`](crate::relay::Error::InsecureConnection).
+    /// Tor onion services are always allowed.
+    pub fn allow_insecure(self, allow: bool) -> Self {
+        Self {
+            allow_insecure: allow,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_allow_insecure(&self) -> bool {
+        self.allow_insecure
+    }
+
+    /// Set time to live of the cached `get_events_of` results
+    ///
+    /// When set, the same filters requested again within the TTL are served from a per-relay LRU cache,
+    /// without a round-trip to the relay. Clear it with [`Relay::clear_query_cache`](crate::Relay::clear_query_cache).
+    pub fn query_cache_ttl(self, ttl: Option<Duration>) -> Self {
+        Self {
+            query_cache_ttl: ttl,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_query_cache_ttl(&self) -> Option<Duration> {
+        self.query_cache_ttl
+    }
+
+    /// Set max number of cached `get_events_of` results
+    pub fn query_cache_size(self, size: usize) -> Self {
+        Self {
+            query_cache_size: size,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_query_cache_size(&self) -> usize {
+        self.query_cache_size
+    }
+
+    /// Set weights of [`Relay::score`](crate::Relay::score)
+    pub fn scoring_weights(self, scoring_weights: ScoringWeights) -> Self {
+        Self {
+            scoring_weights,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_scoring_weights(&self) -> ScoringWeights {
+        self.scoring_weights
+    }
+
+    /// Set max number of one-shot queries (`get_events_of`, `req_events_of`) running at the same time
+    ///
+    /// Excess queries wait for a free slot: their `timeout` starts only once running.
+    pub fn max_concurrent_queries(self, max: Option<usize>) -> Self {
+        Self {
+            max_concurrent_queries: max,
+            ..self
+        }
+    }
+
+    pub(crate) fn get_max_concurrent_queries(&self) -> Option<usize> {
+        self.max_concurrent_queries
+    }
+
+    /// Set rate limit of the outgoing `EVENT` messages (token bucket)
+    ///
+    /// `events_per_second` tokens are added every second, up to `burst`. When the bucket is empty,
+    /// [`Relay::send_msg`](crate::Relay::send_msg) and [`Relay::batch_msg`](crate::Relay::batch_msg)
+    /// wait for a token, up to their `wait` timeout. A rate not greater than zero disables the limit.
+    pub fn rate_limit(self, events_per_second: f64, burst: usize) -> Self {
+        let rate_limit = if events_per_second > 0.0 && events_per_second.is_finite() {
+            Some((events_per_second, burst.max(1)))
+        } else {
+            None
+        };
+        Self { rate_limit, ..self }
+    }
+
+    pub(crate) fn get_rate_limit(&self) -> Option<(f64, usize)> {
+        self.rate_limit
+    }
+
+    /// Set number of sockets opened to the relay
+    ///
+    /// The outgoing `EVENT` messages sent without waiting are load-balanced across the sockets,
+    /// while the subscriptions stay on the main one, to keep the `EOSE` semantics.
+    /// The incoming messages of all the sockets are merged. An additional socket that fails is
+    /// opened again only at the next reconnection. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn connections(self, connections: usize) -> Self {
+        Self {
+            connections: connections.max(1),
+            ..self
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn get_connections(&self) -> usize {
+        self.connections
+    }
+
+    /// Set fallback proxies
+    ///
+    /// Tried in order, after the proxy passed to the [`Relay`](crate::Relay), until one succeeds.
+    /// If the relay has no proxy, only these are used (the relay is never dialed directly).
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn proxies(self, proxies: Vec<SocketAddr>) -> Self {
+        Self { proxies, ..self }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn get_proxies(&self) -> &[SocketAddr] {
+        &self.proxies
+    }
+
+    /// Set custom WebSocket transport
+    ///
+    /// Replace the TCP/TLS connection to the relay: the proxies and the [`ConnectionConfig`] are ignored.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn transport(self, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport: Some(transport),
+            ..self
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn get_transport(&self) -> Option<Arc<dyn Transport>> {
+        self.transport.clone()
+    }
+
+    /// Set custom DNS resolver
+    ///
+    /// Used to resolve the relay host when connecting directly. Ignored when connecting through a proxy,
+    /// since the proxy resolves the host (no DNS query is made locally).
+    ///
+    /// Tor users shouldn't rely on a custom resolver: connect through the Tor SOCKS proxy instead,
+    /// to not leak which relays they connect to via DNS.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn resolver(self, resolver: Arc<dyn Resolver>) -> Self {
+        Self {
+            resolver: Some(resolver),
+            ..self
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn get_resolver(&self) -> Option<&dyn Resolver> {
+        self.resolver.as_deref()
+    }
+
+    pub(crate) fn notification_capacity(self, capacity: usize) -> Self {
+        Self {
+            notification_capacity: Some(capacity),
+            ..self
+        }
+    }
+
+    pub(crate) fn get_notification_capacity(&self) -> Option<usize> {
+        self.notification_capacity
+    }
+
+    /// Get the interval to wait before the next reconnection attempt
+    pub(crate) fn get_retry_interval(&self, consecutive_failures: usize) -> Duration {
+        self.retry_interval_with_jitter(consecutive_failures, rand::random())
+    }
+
+    /// Get the retry interval, with `jitter` in `[0, 1)` picking the random factor (if enabled)
+    fn retry_interval_with_jitter(&self, consecutive_failures: usize, jitter: f64) -> Duration {
+        let min: f64 = self.min_retry_interval.as_secs_f64();
+        let max: f64 = self.max_retry_interval.as_secs_f64().max(min);
+        let multiplier: f64 = if self.retry_multiplier.is_nan() {
+            1.0
+        } else {
+            self.retry_multiplier.max(1.0)
+        };
+        let exp: i32 = consecutive_failures
+            .saturating_sub(1)
+            .min(i32::MAX as usize) as i32;
+        let mut secs: f64 = min * multiplier.powi(exp);
+        if !secs.is_finite() || secs > max {
+            secs = max;
+        }
+        if self.retry_jitter {
+            secs *= 0.75 + jitter * 0.5;
+        }
+        Duration::from_secs_f64(secs.min(max))
+    }
 }
 
-impl Default for RelayOptions {
-    fn default() -> Self {
-        Self::new(true, true)
+/// Builder of [`RelayOptions`]
+///
+/// Start from the defaults ([`RelayOptions::default`]: read and write enabled) and set only the options to change.
+#[derive(Debug, Clone, Default)]
+pub struct RelayOptionsBuilder {
+    opts: RelayOptions,
+}
+
+impl RelayOptionsBuilder {
+    /// New builder with default options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build [`RelayOptions`]
+    pub fn build(self) -> RelayOptions {
+        self.opts
+    }
+
+    /// Set read option
+    pub fn read(self, read: bool) -> Self {
+        self.opts.set_read(read);
+        self
+    }
+
+    /// Set write option
+    pub fn write(self, write: bool) -> Self {
+        self.opts.set_write(write);
+        self
+    }
+
+    /// See [`RelayOptions::ip_version`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn ip_version(self, ip_version: IpPreference) -> Self {
+        Self {
+            opts: self.opts.ip_version(ip_version),
+        }
+    }
+
+    /// See [`RelayOptions::max_memory`]
+    pub fn max_memory(self, max_memory: Option<usize>) -> Self {
+        Self {
+            opts: self.opts.max_memory(max_memory),
+        }
+    }
+
+    /// See [`RelayOptions::message_serializer`]
+    pub fn message_serializer(self, serializer: Option<MessageSerializer>) -> Self {
+        Self {
+            opts: self.opts.message_serializer(serializer),
+        }
+    }
+
+    /// See [`RelayOptions::on_raw_message`]
+    pub fn on_raw_message(self, hook: Option<RawMessageHook>) -> Self {
+        Self {
+            opts: self.opts.on_raw_message(hook),
+        }
+    }
+
+    /// See [`RelayOptions::loop_max_lifetime`]
+    pub fn loop_max_lifetime(self, lifetime: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.loop_max_lifetime(lifetime),
+        }
+    }
+
+    /// See [`RelayOptions::retry_interval`]
+    pub fn retry_interval(self, interval: Duration) -> Self {
+        Self {
+            opts: self.opts.retry_interval(interval),
+        }
     }
-}
 
-impl RelayOptions {
-    /// New [`RelayOptions`]
-    pub fn new(read: bool, write: bool) -> Self {
+    /// See [`RelayOptions::min_retry_interval`]
+    pub fn min_retry_interval(self, interval: Duration) -> Self {
         Self {
-            read: Arc::new(AtomicBool::new(read)),
-            write: Arc::new(AtomicBool::new(write)),
+            opts: self.opts.min_retry_interval(interval),
         }
     }
 
-    /// Get read option
-    pub fn read(&self) -> bool {
-        self.read.load(Ordering::SeqCst)
+    /// See [`RelayOptions::max_retry_interval`]
+    pub fn max_retry_interval(self, interval: Duration) -> Self {
+        Self {
+            opts: self.opts.max_retry_interval(interval),
+        }
     }
 
-    /// Set read option
-    pub fn set_read(&self, read: bool) {
-        let _ = self
-            .read
-            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| Some(read));
+    /// See [`RelayOptions::retry_multiplier`]
+    pub fn retry_multiplier(self, multiplier: f64) -> Self {
+        Self {
+            opts: self.opts.retry_multiplier(multiplier),
+        }
     }
 
-    /// Get write option
-    pub fn write(&self) -> bool {
-        self.write.load(Ordering::SeqCst)
+    /// See [`RelayOptions::retry_jitter`]
+    pub fn retry_jitter(self, jitter: bool) -> Self {
+        Self {
+            opts: self.opts.retry_jitter(jitter),
+        }
     }
 
-    /// Set write option
-    pub fn set_write(&self, write: bool) {
-        let _ = self
-            .write
-            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |_| Some(write));
+    /// See [`RelayOptions::reject_backwards`]
+    pub fn reject_backwards(self, window: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.reject_backwards(window),
+        }
+    }
+
+    /// See [`RelayOptions::ping_interval`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn ping_interval(self, interval: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.ping_interval(interval),
+        }
+    }
+
+    /// See [`RelayOptions::document_ttl`]
+    #[cfg(feature = "nip11")]
+    pub fn document_ttl(self, ttl: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.document_ttl(ttl),
+        }
+    }
+
+    /// See [`RelayOptions::nip11_timeout`]
+    #[cfg(feature = "nip11")]
+    pub fn nip11_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.nip11_timeout(timeout),
+        }
+    }
+
+    /// See [`RelayOptions::default_send_timeout`]
+    pub fn default_send_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.default_send_timeout(timeout),
+        }
+    }
+
+    /// See [`RelayOptions::connection_timeout`]
+    pub fn connection_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.connection_timeout(timeout),
+        }
+    }
+
+    /// See [`RelayOptions::auto_auth`]
+    pub fn auto_auth(self, keys: Option<Keys>) -> Self {
+        Self {
+            opts: self.opts.auto_auth(keys),
+        }
+    }
+
+    /// See [`RelayOptions::queue_capacity`]
+    pub fn queue_capacity(self, capacity: usize) -> Self {
+        Self {
+            opts: self.opts.queue_capacity(capacity),
+        }
+    }
+
+    /// See [`RelayOptions::queue_overflow_policy`]
+    pub fn queue_overflow_policy(self, policy: QueueOverflowPolicy) -> Self {
+        Self {
+            opts: self.opts.queue_overflow_policy(policy),
+        }
+    }
+
+    /// See [`RelayOptions::disabled_policy`]
+    pub fn disabled_policy(self, policy: DisabledPolicy) -> Self {
+        Self {
+            opts: self.opts.disabled_policy(policy),
+        }
+    }
+
+    /// See [`RelayOptions::store_and_forward`]
+    pub fn store_and_forward(self, enable: bool) -> Self {
+        Self {
+            opts: self.opts.store_and_forward(enable),
+        }
+    }
+
+    /// See [`RelayOptions::max_queued_age`]
+    pub fn max_queued_age(self, age: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.max_queued_age(age),
+        }
+    }
+
+    /// See [`RelayOptions::dedup_events`]
+    pub fn dedup_events(self, dedup: bool) -> Self {
+        Self {
+            opts: self.opts.dedup_events(dedup),
+        }
+    }
+
+    /// See [`RelayOptions::verify_events`]
+    pub fn verify_events(self, verify: bool) -> Self {
+        Self {
+            opts: self.opts.verify_events(verify),
+        }
+    }
+
+    /// See [`RelayOptions::enforce_filter_match`]
+    pub fn enforce_filter_match(self, enforce: bool) -> Self {
+        Self {
+            opts: self.opts.enforce_filter_match(enforce),
+        }
+    }
+
+    /// See [`RelayOptions::max_message_size`]
+    pub fn max_message_size(self, size: Option<usize>) -> Self {
+        Self {
+            opts: self.opts.max_message_size(size),
+        }
+    }
+
+    /// See [`RelayOptions::split_threshold`]
+    pub fn split_threshold(self, threshold: Option<usize>) -> Self {
+        Self {
+            opts: self.opts.split_threshold(threshold),
+        }
+    }
+
+    /// See [`RelayOptions::auto_resubscribe`]
+    pub fn auto_resubscribe(self, auto_resubscribe: bool) -> Self {
+        Self {
+            opts: self.opts.auto_resubscribe(auto_resubscribe),
+        }
+    }
+
+    /// See [`RelayOptions::disconnect_on_read_close`]
+    pub fn disconnect_on_read_close(self, disconnect_on_read_close: bool) -> Self {
+        Self {
+            opts: self.opts.disconnect_on_read_close(disconnect_on_read_close),
+        }
+    }
+
+    /// See [`RelayOptions::stale_timeout`]
+    pub fn stale_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.stale_timeout(timeout),
+        }
+    }
+
+    /// See [`RelayOptions::idle_timeout`]
+    pub fn idle_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.idle_timeout(timeout),
+        }
+    }
+
+    /// See [`RelayOptions::reconnect_on_stale`]
+    pub fn reconnect_on_stale(self, reconnect: bool) -> Self {
+        Self {
+            opts: self.opts.reconnect_on_stale(reconnect),
+        }
+    }
+
+    /// See [`RelayOptions::pause_ingestion_on_lag`]
+    pub fn pause_ingestion_on_lag(self, pause: bool) -> Self {
+        Self {
+            opts: self.opts.pause_ingestion_on_lag(pause),
+        }
+    }
+
+    /// See [`RelayOptions::connection_config`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn connection_config(self, connection_config: ConnectionConfig) -> Self {
+        Self {
+            opts: self.opts.connection_config(connection_config),
+        }
     }
+
+    /// See [`RelayOptions::headers`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn headers(self, headers: HashMap<String, String>) -> Self {
+        Self {
+            opts: self.opts.headers(headers),
+        }
+    }
+
+    /// See [`RelayOptions::allow_insecure`]
+    pub fn allow_insecure(self, allow: bool) -> Self {
+        Self {
+            opts: self.opts.allow_insecure(allow),
+        }
+    }
+
+    /// See [`RelayOptions::query_cache_ttl`]
+    pub fn query_cache_ttl(self, ttl: Option<Duration>) -> Self {
+        Self {
+            opts: self.opts.query_cache_ttl(ttl),
+        }
+    }
+
+    /// See [`RelayOptions::query_cache_size`]
+    pub fn query_cache_size(self, size: usize) -> Self {
+        Self {
+            opts: self.opts.query_cache_size(size),
+        }
+    }
+
+    /// See [`RelayOptions::scoring_weights`]
+    pub fn scoring_weights(self, scoring_weights: ScoringWeights) -> Self {
+        Self {
+            opts: self.opts.scoring_weights(scoring_weights),
+        }
+    }
+
+    /// See [`RelayOptions::max_concurrent_queries`]
+    pub fn max_concurrent_queries(self, max: Option<usize>) -> Self {
+        Self {
+            opts: self.opts.max_concurrent_queries(max),
+        }
+    }
+
+    /// See [`RelayOptions::rate_limit`]
+    pub fn rate_limit(self, events_per_second: f64, burst: usize) -> Self {
+        Self {
+            opts: self.opts.rate_limit(events_per_second, burst),
+        }
+    }
+
+    /// See [`RelayOptions::connections`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn connections(self, connections: usize) -> Self {
+        Self {
+            opts: self.opts.connections(connections),
+        }
+    }
+
+    /// See [`RelayOptions::proxies`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn proxies(self, proxies: Vec<SocketAddr>) -> Self {
+        Self {
+            opts: self.opts.proxies(proxies),
+        }
+    }
+
+    /// See [`RelayOptions::transport`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn transport(self, transport: Arc<dyn Transport>) -> Self {
+        Self {
+            opts: self.opts.transport(transport),
+        }
+    }
+
+    /// See [`RelayOptions::resolver`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn resolver(self, resolver: Arc<dyn Resolver>) -> Self {
+        Self {
+            opts: self.opts.resolver(resolver),
+        }
+    }
+}
+
+/// Weights of [`Relay::score`](crate::Relay::score)
+///
+/// Every factor is normalized in `0..=1` and the score is their weighted average:
+///
+/// * `success`: successful connections / attempts (0 if never attempted)
+/// * `latency`: `reference / (reference + latency)` with `latency_reference` (0.5 if not measured yet)
+/// * `failures`: `1 / (1 + consecutive failures)`
+/// * `freshness`: `reference / (reference + time since last data received)` with `freshness_reference` (0 if nothing received)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoringWeights {
+    /// Weight of the connection success ratio (default: 0.4)
+    pub success: f64,
+    /// Weight of the latency (default: 0.3)
+    pub latency: f64,
+    /// Weight of the consecutive connection failures (default: 0.2)
+    pub failures: f64,
+    /// Weight of the time since the last data received (default: 0.1)
+    pub freshness: f64,
+    /// Latency that halves the latency factor (default: 500 ms)
+    pub latency_reference: Duration,
+    /// Time since the last data received that halves the freshness factor (default: 60 secs)
+    pub freshness_reference: Duration,
+}
+
+impl Default for ScoringWeights {
+    fn default() -> Self {
+        Self {
+            success: 0.4,
+            latency: 0.3,
+            failures: 0.2,
+            freshness: 0.1,
+            latency_reference: Duration::from_millis(500),
+            freshness_reference: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Behavior of the read/write actions when disabled by [`RelayOptions`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisabledPolicy {
+    /// Return [`Error::ReadDisabled`](crate::relay::Error::ReadDisabled) or [`Error::WriteDisabled`](crate::relay::Error::WriteDisabled)
+    #[default]
+    Error,
+    /// Skip the action, returning `Ok` with an empty result
+    Skip,
+}
+
+/// How far to wait when sending an [`Event`](nostr::Event)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SendConfirmation {
+    /// Return as soon as the message is queued for the relay
+    Queued,
+    /// Return when the message is written to the socket
+    ///
+    /// Doesn't mean that the relay received or accepted it: this is what the `wait` of `Relay::send_msg` confirms.
+    Written,
+    /// Wait for the `OK` message of the relay
+    #[default]
+    Acknowledged,
 }
 
 /// [`Relay`] send options
 #[derive(Debug, Clone, Copy)]
 pub struct RelaySendOptions {
     /// Timeout for sending event (default: 30 secs)
+    ///
+    /// If `None`, the [`RelayOptions::default_send_timeout`] of the relay is used.
     pub timeout: Option<Duration>,
+    /// Max number of events sent at once by `batch_event` (default: 500)
+    ///
+    /// Bigger batches are split in chunks, waiting for the `OK` messages of a chunk before sending the next one.
+    pub batch_size: usize,
+    /// How far to wait when sending events (default: [`SendConfirmation::Acknowledged`])
+    pub confirmation: SendConfirmation,
+    /// Number of times an event is sent again after a transient rejection (default: 0)
+    ///
+    /// The timeout is applied to each attempt.
+    pub retries: u8,
+    /// Delay between the retries (default: 1 sec)
+    pub retry_delay: Duration,
 }
 
 impl Default for RelaySendOptions {
     fn default() -> Self {
         Self {
             timeout: Some(Duration::from_secs(30)),
+            batch_size: 500,
+            confirmation: SendConfirmation::default(),
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
         }
     }
 }
@@ -77,12 +1397,94 @@ impl RelaySendOptions {
 
     /// Timeout for sending event
     pub fn timeout(self, value: Option<Duration>) -> Self {
-        Self { timeout: value }
+        Self {
+            timeout: value,
+            ..self
+        }
+    }
+
+    /// Max number of events sent at once by `batch_event`
+    pub fn batch_size(self, value: usize) -> Self {
+        Self {
+            batch_size: value,
+            ..self
+        }
+    }
+
+    /// How far to wait when sending events
+    pub fn confirmation(self, value: SendConfirmation) -> Self {
+        Self {
+            confirmation: value,
+            ..self
+        }
+    }
+
+    /// Number of times an event is sent again after a transient rejection (i.e. `rate-limited:`)
+    pub fn retries(self, value: u8) -> Self {
+        Self {
+            retries: value,
+            ..self
+        }
+    }
+
+    /// Delay between the retries
+    pub fn retry_delay(self, value: Duration) -> Self {
+        Self {
+            retry_delay: value,
+            ..self
+        }
+    }
+}
+
+/// [`Relay`] subscribe options
+///
+/// Used by [`Relay::subscribe_with_internal_id_opts`](crate::Relay::subscribe_with_internal_id_opts).
+#[derive(Debug, Clone, Default)]
+pub struct SubscribeOptions {
+    /// Options applied after EOSE (default: none, keep the subscription open)
+    pub filter_opts: Option<FilterOptions>,
+    /// Send the `REQ` also if the subscription already exists with the same filters and [`FilterOptions`] (default: false)
+    pub force: bool,
+    /// [`SubscriptionId`] of the subscription, used only when it's created (default: random)
+    pub id: Option<SubscriptionId>,
+}
+
+impl SubscribeOptions {
+    /// New default [`SubscribeOptions`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Close the subscription after EOSE according to the [`FilterOptions`]
+    pub fn filter_opts(self, value: FilterOptions) -> Self {
+        Self {
+            filter_opts: Some(value),
+            ..self
+        }
+    }
+
+    /// Send the `REQ` also if the subscription is unchanged
+    pub fn force(self, value: bool) -> Self {
+        Self {
+            force: value,
+            ..self
+        }
+    }
+
+    /// Use a custom [`SubscriptionId`] instead of a random one
+    ///
+    /// Subscribing fails with [`Error::SubscriptionIdCollision`](crate::relay::Error::SubscriptionIdCollision)
+    /// if the id is already used by another subscription.
+    pub fn id(self, id: SubscriptionId) -> Self {
+        Self {
+            id: Some(id),
+            ..self
+        }
     }
 }
 
 /// Filter options
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum FilterOptions {
     /// Exit on EOSE
     #[default]
@@ -91,6 +1493,14 @@ pub enum FilterOptions {
     WaitForEventsAfterEOSE(u16),
     /// After EOSE is received, keep listening for matching events for [`Duration`] more time, then return
     WaitDurationAfterEOSE(Duration),
+    /// After EOSE is received, keep listening for up to N more events or for [`Duration`] more time,
+    /// whichever comes first, then return
+    WaitAfterEOSE {
+        /// Max number of events to wait after EOSE
+        max_events: usize,
+        /// Max time to wait after EOSE
+        max_duration: Duration,
+    },
 }
 
 /// Relay Pool Options
@@ -126,6 +1536,17 @@ impl RelayPoolOptions {
         Self::default()
     }
 
+    /// Set notification channel size
+    ///
+    /// A slow consumer of [`RelayPool::notifications`] that doesn't keep up with the received messages
+    /// will skip the oldest notifications: increase it under heavy load.
+    pub fn notification_channel_size(self, size: usize) -> Self {
+        Self {
+            notification_channel_size: size.max(1),
+            ..self
+        }
+    }
+
     /// Shutdown on [`RelayPool`] drop
     pub fn shutdown_on_drop(self, value: bool) -> Self {
         Self {
@@ -134,3 +1555,82 @@ impl RelayPoolOptions {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_interval_growth() {
+        let opts = RelayOptions::default()
+            .min_retry_interval(Duration::from_secs(5))
+            .max_retry_interval(Duration::from_secs(300))
+            .retry_multiplier(2.0)
+            .retry_jitter(false);
+        let intervals: Vec<u64> = (1..=4)
+            .map(|failures| opts.get_retry_interval(failures).as_secs())
+            .collect();
+        assert_eq!(intervals, vec![5, 10, 20, 40]);
+        // No failure yet: the min interval
+        assert_eq!(opts.get_retry_interval(0), Duration::from_secs(5));
+
+        // Multiplier lower than 1 (or NaN): constant interval
+        for multiplier in [0.5, f64::NAN] {
+            let opts = opts.clone().retry_multiplier(multiplier);
+            assert_eq!(opts.get_retry_interval(10), Duration::from_secs(5));
+        }
+    }
+
+    #[test]
+    fn test_retry_interval_cap() {
+        let opts = RelayOptions::default()
+            .min_retry_interval(Duration::from_secs(5))
+            .max_retry_interval(Duration::from_secs(60))
+            .retry_multiplier(2.0)
+            .retry_jitter(false);
+        assert_eq!(opts.get_retry_interval(5), Duration::from_secs(60));
+        // Overflowing exponent
+        assert_eq!(opts.get_retry_interval(usize::MAX), Duration::from_secs(60));
+
+        // Max lower than min: the min interval
+        let opts = opts.max_retry_interval(Duration::from_secs(1));
+        assert_eq!(opts.get_retry_interval(3), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retry_interval_jitter() {
+        let opts = RelayOptions::default()
+            .min_retry_interval(Duration::from_secs(8))
+            .max_retry_interval(Duration::from_secs(20))
+            .retry_multiplier(2.0)
+            .retry_jitter(true);
+
+        // +/- 25%
+        assert_eq!(
+            opts.retry_interval_with_jitter(1, 0.0),
+            Duration::from_secs(6)
+        );
+        assert_eq!(
+            opts.retry_interval_with_jitter(1, 0.5),
+            Duration::from_secs(8)
+        );
+        assert!(opts.retry_interval_with_jitter(1, 0.999) < Duration::from_secs(10));
+
+        // Never above the max
+        assert_eq!(
+            opts.retry_interval_with_jitter(3, 0.0),
+            Duration::from_secs(15)
+        );
+        assert_eq!(
+            opts.retry_interval_with_jitter(3, 0.999),
+            Duration::from_secs(20)
+        );
+
+        // Disabled: the factor is ignored
+        let opts = opts.retry_jitter(false);
+        assert_eq!(
+            opts.retry_interval_with_jitter(1, 0.0),
+            Duration::from_secs(8)
+        );
+    }
+}
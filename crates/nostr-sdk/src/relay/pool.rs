@@ -12,16 +12,16 @@ use std::time::Duration;
 
 use async_utility::thread;
 use nostr::url::Url;
-use nostr::{ClientMessage, Event, EventId, Filter, RelayMessage};
+use nostr::{ClientMessage, Event, EventId, Filter, RelayMessage, SubscriptionId};
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tokio::sync::{broadcast, Mutex};
 
-use crate::util::TryIntoUrl;
+use crate::util::{normalize_url, TryIntoUrl};
 
 use super::options::RelayPoolOptions;
 use super::{
-    Error as RelayError, FilterOptions, InternalSubscriptionId, Relay, RelayOptions,
-    RelaySendOptions,
+    DisconnectReason, Error as RelayError, FilterOptions, InternalSubscriptionId, Relay,
+    RelayOptions, RelaySendOptions, RelayStatus, SubscribeReason,
 };
 
 /// [`RelayPool`] error
@@ -81,6 +81,72 @@ pub enum RelayPoolNotification {
     Event(Url, Event),
     /// Received a [`RelayMessage`]. Includes messages wrapping events that were sent by this client.
     Message(Url, RelayMessage),
+    /// Relay status changed
+    RelayStatus {
+        /// Relay url
+        url: Url,
+        /// Previous status
+        previous: RelayStatus,
+        /// New status
+        status: RelayStatus,
+        /// Disconnection reason, if the relay has been disconnected
+        reason: Option<DisconnectReason>,
+    },
+    /// Received the EOSE message for a subscription
+    ///
+    /// Emitted for both long-lived subscriptions and the ones of [`Relay::get_events_of`](crate::Relay::get_events_of)
+    EndOfStoredEvents {
+        /// Relay url
+        url: Url,
+        /// Subscription ID
+        subscription_id: SubscriptionId,
+    },
+    /// Received a `NOTICE` message
+    Notice {
+        /// Relay url
+        url: Url,
+        /// Message
+        message: String,
+    },
+    /// Received a `CLOSED` message: the relay closed the subscription
+    SubscriptionClosed {
+        /// Relay url
+        url: Url,
+        /// Subscription ID
+        subscription_id: SubscriptionId,
+        /// Reason (i.e. `auth-required: ...`)
+        message: String,
+    },
+    /// Received a message that can't be parsed (malformed or not supported)
+    MessageError {
+        /// Relay url
+        url: Url,
+        /// Parsing error
+        error: String,
+        /// Raw message
+        raw: String,
+    },
+    /// `REQ` of a subscription sent to the relay
+    Subscribed {
+        /// Relay url
+        url: Url,
+        /// Internal subscription ID
+        internal_id: InternalSubscriptionId,
+        /// Why the `REQ` has been sent
+        reason: SubscribeReason,
+    },
+    /// `CLOSE` of a subscription sent to the relay
+    Unsubscribed {
+        /// Relay url
+        url: Url,
+        /// Internal subscription ID
+        internal_id: InternalSubscriptionId,
+    },
+    /// All the subscriptions of the relay received the EOSE message
+    AllCaughtUp {
+        /// Relay url
+        url: Url,
+    },
     /// Stop
     Stop,
     /// Shutdown
@@ -145,6 +211,38 @@ impl RelayPoolTask {
                                     msg.clone(),
                                 ));
 
+                            if let RelayMessage::EndOfStoredEvents(subscription_id) = &msg {
+                                let _ = this.notification_sender.send(
+                                    RelayPoolNotification::EndOfStoredEvents {
+                                        url: relay_url.clone(),
+                                        subscription_id: subscription_id.clone(),
+                                    },
+                                );
+                            }
+
+                            if let RelayMessage::Notice { message } = &msg {
+                                let _ =
+                                    this.notification_sender
+                                        .send(RelayPoolNotification::Notice {
+                                            url: relay_url.clone(),
+                                            message: message.clone(),
+                                        });
+                            }
+
+                            if let RelayMessage::Closed {
+                                subscription_id,
+                                message,
+                            } = &msg
+                            {
+                                let _ = this.notification_sender.send(
+                                    RelayPoolNotification::SubscriptionClosed {
+                                        url: relay_url.clone(),
+                                        subscription_id: subscription_id.clone(),
+                                        message: message.clone(),
+                                    },
+                                );
+                            }
+
                             if let RelayMessage::Event { event, .. } = msg {
                                 // Verifies if the event is valid
                                 if event.verify().is_ok() {
@@ -328,13 +426,24 @@ impl RelayPool {
         relays.clone()
     }
 
+    /// Render the stats of all the relays in Prometheus exposition format, with the relay url as `url` label
+    #[cfg(feature = "metrics")]
+    pub async fn metrics_prometheus(&self) -> String {
+        let relays = self.relays().await;
+        let mut snapshots = Vec::with_capacity(relays.len());
+        for relay in relays.values() {
+            snapshots.push(relay.snapshot().await);
+        }
+        super::metrics::render(&snapshots)
+    }
+
     /// Get [`Relay`]
     pub async fn relay<U>(&self, url: U) -> Result<Relay, Error>
     where
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
-        let url: Url = url.try_into_url()?;
+        let url: Url = normalize_url(&url.try_into_url()?);
         let relays = self.relays.lock().await;
         relays.get(&url).cloned().ok_or(Error::RelayNotFound)
     }
@@ -362,7 +471,7 @@ impl RelayPool {
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
-        let url: Url = url.try_into_url()?;
+        let url: Url = normalize_url(&url.try_into_url()?);
         let mut relays = self.relays.lock().await;
         if !relays.contains_key(&url) {
             let relay = Relay::new(
@@ -370,7 +479,7 @@ impl RelayPool {
                 self.pool_task_sender.clone(),
                 self.notification_sender.clone(),
                 proxy,
-                opts,
+                opts.notification_capacity(self.opts.notification_channel_size),
             );
             relays.insert(relay.url(), relay);
         }
@@ -384,14 +493,14 @@ impl RelayPool {
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
-        let url: Url = url.try_into_url()?;
+        let url: Url = normalize_url(&url.try_into_url()?);
         let mut relays = self.relays.lock().await;
         if !relays.contains_key(&url) {
             let relay = Relay::new(
                 url,
                 self.pool_task_sender.clone(),
                 self.notification_sender.clone(),
-                opts,
+                opts.notification_capacity(self.opts.notification_channel_size),
             );
             relays.insert(relay.url(), relay);
         }
@@ -404,7 +513,7 @@ impl RelayPool {
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
-        let url: Url = url.try_into_url()?;
+        let url: Url = normalize_url(&url.try_into_url()?);
         let mut relays = self.relays.lock().await;
         if let Some(relay) = relays.remove(&url) {
             self.disconnect_relay(&relay).await?;
@@ -528,7 +637,7 @@ impl RelayPool {
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
-        let url: Url = url.try_into_url()?;
+        let url: Url = normalize_url(&url.try_into_url()?);
 
         if let ClientMessage::Event(event) = &msg {
             self.set_events_as_sent(vec![event.id]).await;
@@ -640,7 +749,7 @@ impl RelayPool {
         U: TryIntoUrl,
         Error: From<<U as TryIntoUrl>::Err>,
     {
-        let url: Url = url.try_into_url()?;
+        let url: Url = normalize_url(&url.try_into_url()?);
         self.set_events_as_sent(vec![event.id]).await;
         let relays = self.relays().await;
         if let Some(relay) = relays.get(&url) {
@@ -725,9 +834,17 @@ impl RelayPool {
     }
 
     /// Connect to all added relays and keep connection alive
+    ///
+    /// Relays are connected in order of last handshake duration, fastest first.
     pub async fn connect(&self, wait_for_connection: bool) {
         let relays = self.relays().await;
-        for relay in relays.values() {
+        let mut relays: Vec<Relay> = relays.into_values().collect();
+        relays.sort_by_key(|relay| {
+            let duration: Duration = relay.stats().last_handshake_duration();
+            // Relays never connected go last
+            (duration.is_zero(), duration)
+        });
+        for relay in relays.iter() {
             self.connect_relay(relay, wait_for_connection).await;
         }
     }
@@ -0,0 +1,250 @@
+// Copyright (c) 2022-2023 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! WebSocket transport
+
+use std::fmt;
+use std::pin::Pin;
+
+use nostr::Url;
+use nostr_sdk_net::futures_util::future::BoxFuture;
+use nostr_sdk_net::futures_util::{Sink, SinkExt, Stream, TryStreamExt};
+pub use nostr_sdk_net::native::Error as TransportError;
+use nostr_sdk_net::{self as net, WsMessage};
+
+/// Write half of a WebSocket transport
+pub type WsSink = Pin<Box<dyn Sink<WsMessage, Error = TransportError> + Send>>;
+
+/// Read half of a WebSocket transport
+pub type WsStream = Pin<Box<dyn Stream<Item = Result<WsMessage, TransportError>> + Send>>;
+
+/// WebSocket transport
+///
+/// Set it with [`RelayOptions::transport`](super::RelayOptions::transport) to replace the
+/// TCP/TLS connection to the relay (i.e. with an in-memory channel in tests).
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Open a WebSocket to the relay
+    fn connect<'a>(
+        &'a self,
+        url: &'a Url,
+    ) -> BoxFuture<'a, Result<(WsSink, WsStream), TransportError>>;
+}
+
+/// Box the halves of a native WebSocket
+pub(crate) fn boxed(sink: net::native::Sink, stream: net::native::Stream) -> (WsSink, WsStream) {
+    (
+        Box::pin(sink.sink_map_err(TransportError::from)),
+        Box::pin(stream.map_err(TransportError::from)),
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod memory {
+    use std::sync::Arc;
+
+    use nostr::{ClientMessage, Event, RelayMessage, SubscriptionId};
+    use nostr_sdk_net::futures_util::{sink, stream};
+    use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+    use super::*;
+    use crate::relay::pool::RelayPool;
+    use crate::relay::{Relay, RelayOptions, RelayPoolOptions};
+
+    /// Relay side of an in-memory WebSocket
+    pub(crate) struct MemorySocket {
+        /// Messages sent by the client
+        pub incoming: UnboundedReceiver<WsMessage>,
+        /// Messages to send to the client
+        pub outgoing: UnboundedSender<WsMessage>,
+    }
+
+    impl MemorySocket {
+        /// Receive the next message sent by the client
+        pub async fn recv(&mut self) -> ClientMessage {
+            let msg: WsMessage = self.incoming.recv().await.expect("socket closed");
+            ClientMessage::from_json(msg.into_text().unwrap()).unwrap()
+        }
+
+        /// Send a message to the client
+        pub fn send(&self, msg: RelayMessage) {
+            self.outgoing.send(WsMessage::Text(msg.as_json())).unwrap();
+        }
+
+        /// Answer the next `EVENT` with an `OK`, returning the event
+        pub async fn answer_event(&mut self, status: bool, message: &str) -> Event {
+            match self.recv().await {
+                ClientMessage::Event(event) => {
+                    self.send(RelayMessage::new_ok(event.id, status, message));
+                    *event
+                }
+                msg => panic!("unexpected message: {msg:?}"),
+            }
+        }
+
+        /// Answer the next `REQ` with the events, followed by `EOSE`, returning its [`SubscriptionId`]
+        ///
+        /// The other messages received before the `REQ` are skipped.
+        pub async fn answer_req(&mut self, events: &[Event]) -> SubscriptionId {
+            loop {
+                if let ClientMessage::Req {
+                    subscription_id, ..
+                } = self.recv().await
+                {
+                    for event in events {
+                        self.send(RelayMessage::new_event(
+                            subscription_id.clone(),
+                            event.clone(),
+                        ));
+                    }
+                    self.send(RelayMessage::new_eose(subscription_id.clone()));
+                    return subscription_id;
+                }
+            }
+        }
+    }
+
+    /// [`Relay`] connected through a [`MemoryTransport`]
+    pub(crate) struct MemoryRelay {
+        /// Keeps the notification channel of the relay open
+        pub pool: RelayPool,
+        pub relay: Relay,
+        /// Main socket
+        pub socket: MemorySocket,
+        /// Sockets opened after the main one (i.e. additional connections or reconnections)
+        pub sockets: UnboundedReceiver<MemorySocket>,
+    }
+
+    impl MemoryRelay {
+        /// Add the relay to a new [`RelayPool`] and connect it
+        pub async fn connect(opts: RelayOptions) -> Self {
+            let (transport, mut sockets) = MemoryTransport::new();
+            // IP literal, not listening: the NIP-11 document fetched on connection fails right away,
+            // without a DNS resolution that the runtime would wait for at shutdown
+            let url = Url::parse("wss://127.0.0.1:1").unwrap();
+            let pool = RelayPool::new(RelayPoolOptions::default());
+            let opts = opts.transport(Arc::new(transport));
+            pool.add_relay(url.clone(), None, opts).await.unwrap();
+            let relay = pool.relay(url).await.unwrap();
+            relay.connect(true).await;
+            let socket = sockets.recv().await.unwrap();
+            Self {
+                pool,
+                relay,
+                socket,
+                sockets,
+            }
+        }
+
+        /// Send a marker `CLOSE` through the relay and return the messages received on the main socket before it
+        ///
+        /// Used to check what has been sent, without waiting for an arbitrary time.
+        pub async fn flush(&mut self) -> Vec<ClientMessage> {
+            let marker = SubscriptionId::new("flush");
+            self.relay
+                .send_msg(ClientMessage::close(marker.clone()), None)
+                .await
+                .unwrap();
+            let mut msgs = Vec::new();
+            loop {
+                match self.socket.recv().await {
+                    ClientMessage::Close(id) if id == marker => return msgs,
+                    msg => msgs.push(msg),
+                }
+            }
+        }
+    }
+
+    /// In-memory transport: the relay side of every connection is delivered to the receiver returned by [`MemoryTransport::new`]
+    #[derive(Debug)]
+    pub(crate) struct MemoryTransport {
+        sockets: UnboundedSender<MemorySocket>,
+    }
+
+    impl MemoryTransport {
+        pub fn new() -> (Self, UnboundedReceiver<MemorySocket>) {
+            let (sockets, receiver) = mpsc::unbounded_channel();
+            (Self { sockets }, receiver)
+        }
+    }
+
+    impl Transport for MemoryTransport {
+        fn connect<'a>(
+            &'a self,
+            _url: &'a Url,
+        ) -> BoxFuture<'a, Result<(WsSink, WsStream), TransportError>> {
+            Box::pin(async move {
+                let (client_tx, incoming) = mpsc::unbounded_channel();
+                let (outgoing, client_rx) = mpsc::unbounded_channel();
+                self.sockets
+                    .send(MemorySocket { incoming, outgoing })
+                    .map_err(|_| TransportError::Timeout)?;
+
+                let ws_tx: WsSink = Box::pin(sink::unfold(
+                    client_tx,
+                    |client_tx: UnboundedSender<WsMessage>, msg: WsMessage| async move {
+                        client_tx.send(msg).map_err(|_| {
+                            TransportError::IO(std::io::ErrorKind::BrokenPipe.into())
+                        })?;
+                        Ok::<_, TransportError>(client_tx)
+                    },
+                ));
+                let ws_rx: WsStream = Box::pin(stream::unfold(client_rx, |mut client_rx| async {
+                    let msg = client_rx.recv().await?;
+                    Some((Ok(msg), client_rx))
+                }));
+                Ok((ws_tx, ws_rx))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nostr::{EventBuilder, Filter, Keys};
+
+    use super::memory::MemoryRelay;
+    use crate::relay::{FilterOptions, RelayOptions, RelaySendOptions};
+
+    #[tokio::test]
+    async fn test_memory_transport_send_event() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+        assert!(mem.relay.is_connected().await);
+
+        let keys = Keys::generate();
+        let event = EventBuilder::new_text_note("test", &[])
+            .to_event(&keys)
+            .unwrap();
+        let id = event.id;
+
+        let send = mem.relay.send_event(
+            event,
+            RelaySendOptions::new().timeout(Some(Duration::from_secs(5))),
+        );
+        let (res, _) = tokio::join!(send, mem.socket.answer_event(true, ""));
+        assert_eq!(res.unwrap(), id);
+    }
+
+    #[tokio::test]
+    async fn test_memory_transport_get_events_of() {
+        let mut mem = MemoryRelay::connect(RelayOptions::default()).await;
+
+        let keys = Keys::generate();
+        let event = EventBuilder::new_text_note("test", &[])
+            .to_event(&keys)
+            .unwrap();
+        let id = event.id;
+
+        let query = mem.relay.get_events_of(
+            vec![Filter::new().author(keys.public_key().to_string())],
+            Some(Duration::from_secs(5)),
+            FilterOptions::ExitOnEOSE,
+        );
+        let events = vec![event];
+        let (res, _) = tokio::join!(query, mem.socket.answer_req(&events));
+        let events = res.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].id, id);
+    }
+}
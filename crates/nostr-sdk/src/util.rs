@@ -40,3 +40,104 @@ impl TryIntoUrl for &str {
         Url::parse(self)
     }
 }
+
+/// Normalize a relay [`Url`]
+///
+/// The canonical form has a lowercase scheme and host, no default port, no fragment
+/// and no trailing slash (except for the root path, i.e. `wss://relay.example.com/`).
+/// So `wss://Relay.Example.com:443/nostr/` becomes `wss://relay.example.com/nostr`.
+pub fn normalize_url(url: &Url) -> Url {
+    let mut url: Url = url.clone();
+
+    if let Some(host) = url.host_str() {
+        let host: String = host.to_lowercase();
+        if url.set_host(Some(&host)).is_err() {
+            tracing::warn!("Impossible to normalize host of {url}");
+        }
+    }
+
+    if url.port().is_some() && url.port() == default_port(url.scheme()) {
+        let _ = url.set_port(None);
+    }
+
+    url.set_fragment(None);
+
+    let path: String = url.path().trim_end_matches('/').to_string();
+    if path.is_empty() {
+        url.set_path("/");
+    } else {
+        url.set_path(&path);
+    }
+
+    url
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "ws" | "http" => Some(80),
+        "wss" | "https" => Some(443),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(url: &str) -> String {
+        normalize_url(&Url::parse(url).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        // Default port
+        assert_eq!(
+            normalize("wss://relay.example.com:443"),
+            "wss://relay.example.com/"
+        );
+        assert_eq!(
+            normalize("ws://relay.example.com:80/"),
+            "ws://relay.example.com/"
+        );
+        assert_eq!(
+            normalize("wss://relay.example.com:80"),
+            "wss://relay.example.com:80/"
+        );
+
+        // Root path
+        assert_eq!(
+            normalize("wss://relay.example.com"),
+            "wss://relay.example.com/"
+        );
+        assert_eq!(
+            normalize("wss://relay.example.com//"),
+            "wss://relay.example.com/"
+        );
+        assert_eq!(
+            normalize("wss://relay.example.com/nostr/"),
+            "wss://relay.example.com/nostr"
+        );
+
+        // Uppercase scheme and host, path untouched
+        assert_eq!(
+            normalize("WSS://Relay.EXAMPLE.com/Nostr"),
+            "wss://relay.example.com/Nostr"
+        );
+
+        // Fragment
+        assert_eq!(
+            normalize("wss://relay.example.com/#frag"),
+            "wss://relay.example.com/"
+        );
+        assert_eq!(
+            normalize("wss://relay.example.com/nostr#frag"),
+            "wss://relay.example.com/nostr"
+        );
+
+        // All together
+        assert_eq!(
+            normalize("wss://Relay.Example.com:443/nostr/#frag"),
+            "wss://relay.example.com/nostr"
+        );
+    }
+}
@@ -32,6 +32,13 @@ pub enum RelayMessage {
     },
     /// `["EOSE", <subscription_id>]` (NIP01)
     EndOfStoredEvents(SubscriptionId),
+    /// `["CLOSED", <subscription_id>, <message>]` (NIP01)
+    Closed {
+        /// Subscription ID
+        subscription_id: SubscriptionId,
+        /// Message
+        message: String,
+    },
     /// ["NOTICE", <message>] (NIP01)
     Notice {
         /// Message
@@ -95,6 +102,17 @@ impl RelayMessage {
         Self::EndOfStoredEvents(subscription_id)
     }
 
+    /// Create new `CLOSED` message
+    pub fn new_closed<S>(subscription_id: SubscriptionId, message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self::Closed {
+            subscription_id,
+            message: message.into(),
+        }
+    }
+
     /// Create new `OK` message
     pub fn new_ok<S>(event_id: EventId, status: bool, message: S) -> Self
     where
@@ -135,6 +153,10 @@ impl RelayMessage {
             Self::EndOfStoredEvents(subscription_id) => {
                 json!(["EOSE", subscription_id])
             }
+            Self::Closed {
+                subscription_id,
+                message,
+            } => json!(["CLOSED", subscription_id, message]),
             Self::Ok {
                 event_id,
                 status,
@@ -200,6 +222,19 @@ impl RelayMessage {
             return Ok(Self::new_eose(subscription_id));
         }
 
+        // CLOSED
+        // Relay response format: ["CLOSED", <subscription_id>, <message>]
+        if v[0] == "CLOSED" {
+            if v_len != 3 {
+                return Err(MessageHandleError::InvalidMessageFormat);
+            }
+
+            let subscription_id: SubscriptionId = serde_json::from_value(v[1].clone())?;
+            let message: String = serde_json::from_value(v[2].clone())?;
+
+            return Ok(Self::new_closed(subscription_id, message));
+        }
+
         // OK (NIP-20)
         // Relay response format: ["OK", <event_id>, <true|false>, <message>]
         if v[0] == "OK" {
@@ -351,6 +386,33 @@ mod tests {
         assert!(RelayMessage::from_json(r#"["EOSE", 404]"#).is_err(),);
     }
 
+    #[test]
+    fn test_handle_valid_closed() -> Result<()> {
+        let valid_closed_msg =
+            r#"["CLOSED","random-subscription-id","auth-required: not authenticated"]"#;
+        let handled_valid_closed_msg = RelayMessage::new_closed(
+            SubscriptionId::new("random-subscription-id"),
+            "auth-required: not authenticated",
+        );
+
+        assert_eq!(
+            RelayMessage::from_json(valid_closed_msg)?,
+            handled_valid_closed_msg
+        );
+        assert_eq!(handled_valid_closed_msg.as_json(), valid_closed_msg);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_handle_invalid_closed() {
+        // Missing message
+        assert!(RelayMessage::from_json(r#"["CLOSED", "random-subscription-id"]"#).is_err());
+
+        // The message is not string
+        assert!(RelayMessage::from_json(r#"["CLOSED", "random-subscription-id", 404]"#).is_err());
+    }
+
     #[test]
     fn test_handle_valid_ok() -> Result<()> {
         let valid_ok_msg = r#"["OK", "b1a649ebe8b435ec71d3784793f3bbf4b93e64e17568a741aecd4c7ddeafce30", true, "pow: difficulty 25>=24"]"#;
@@ -18,7 +18,7 @@ use serde::ser::{SerializeMap, Serializer};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-use crate::{EventId, Kind, Timestamp};
+use crate::{Event, EventId, Kind, Timestamp};
 
 /// Alphabet Error
 #[derive(Debug)]
@@ -730,6 +730,41 @@ impl Filter {
             ..self
         }
     }
+
+    /// Check if the [`Event`] matches the filter
+    ///
+    /// `ids` and `authors` match by prefix. `search` and `limit` are ignored, since they are evaluated by the relay.
+    pub fn match_event(&self, event: &Event) -> bool {
+        let id: String = event.id.to_hex();
+        let author: String = event.pubkey.to_string();
+        let events: Vec<String> = self.events.iter().map(|id| id.to_hex()).collect();
+        let pubkeys: Vec<String> = self.pubkeys.iter().map(|p| p.to_string()).collect();
+
+        (self.ids.is_empty() || self.ids.iter().any(|prefix| id.starts_with(prefix)))
+            && (self.authors.is_empty()
+                || self.authors.iter().any(|prefix| author.starts_with(prefix)))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+            && self.since.map_or(true, |since| event.created_at >= since)
+            && self.until.map_or(true, |until| event.created_at <= until)
+            && match_tag(event, "e", &events)
+            && match_tag(event, "p", &pubkeys)
+            && match_tag(event, "t", &self.hashtags)
+            && match_tag(event, "r", &self.references)
+            && match_tag(event, "d", &self.identifiers)
+            && self
+                .generic_tags
+                .iter()
+                .all(|(tag, values)| match_tag(event, &tag.to_string(), values))
+    }
+}
+
+/// Check if the event has a `name` tag with one of the `values` (always true if `values` is empty)
+fn match_tag(event: &Event, name: &str, values: &[String]) -> bool {
+    values.is_empty()
+        || event.tags.iter().any(|tag| {
+            let tag: Vec<String> = tag.as_vec();
+            tag.len() >= 2 && tag[0] == name && values.contains(&tag[1])
+        })
 }
 
 fn serialize_generic_tags<S>(
@@ -787,6 +822,7 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{EventBuilder, Keys, Tag};
 
     #[test]
     fn test_kind_concatenation() {
@@ -855,4 +891,37 @@ mod test {
         let filter = Filter::from_json(json).unwrap();
         assert_eq!(filter, Filter::new().search("test"));
     }
+
+    #[test]
+    fn test_match_event() {
+        let keys = Keys::generate();
+        let event = EventBuilder::new_text_note(
+            "test",
+            &[
+                Tag::Hashtag("nostr".to_string()),
+                Tag::Identifier("id".to_string()),
+            ],
+        )
+        .to_event(&keys)
+        .unwrap();
+        let author = keys.public_key().to_string();
+
+        assert!(Filter::new().match_event(&event));
+        assert!(Filter::new()
+            .author(&author[..8])
+            .kind(Kind::TextNote)
+            .hashtag("nostr")
+            .since(event.created_at)
+            .match_event(&event));
+        assert!(Filter::new()
+            .custom_tag(Alphabet::D, vec!["id"])
+            .match_event(&event));
+
+        assert!(!Filter::new().kind(Kind::Metadata).match_event(&event));
+        assert!(!Filter::new().hashtag("bitcoin").match_event(&event));
+        assert!(!Filter::new().pubkey(keys.public_key()).match_event(&event));
+        assert!(!Filter::new()
+            .until(event.created_at - 1_u64)
+            .match_event(&event));
+    }
 }
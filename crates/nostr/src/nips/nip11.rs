@@ -68,6 +68,33 @@ pub struct RelayInformationDocument {
     pub software: Option<String>,
     /// Software version
     pub version: Option<String>,
+    /// Limitations imposed by the relay on clients
+    pub limitation: Option<Limitation>,
+}
+
+/// Limitations imposed by the relay on clients
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Limitation {
+    /// Maximum number of bytes for incoming JSON that the relay will attempt to decode and act upon
+    pub max_message_length: Option<i32>,
+    /// Total number of subscriptions that may be active on a single websocket connection
+    pub max_subscriptions: Option<i32>,
+    /// Maximum number of filter values in each subscription
+    pub max_filters: Option<i32>,
+    /// Relay will clamp each filter's limit value to this number
+    pub max_limit: Option<i32>,
+    /// Maximum length of subscription id as a string
+    pub max_subid_length: Option<i32>,
+    /// Maximum number of elements in the tags list
+    pub max_event_tags: Option<i32>,
+    /// Maximum number of characters in the content field of any event
+    pub max_content_length: Option<i32>,
+    /// New events will require at least this difficulty of PoW
+    pub min_pow_difficulty: Option<i32>,
+    /// Relay requires NIP-42 authentication to happen before a new connection may perform any other action
+    pub auth_required: Option<bool>,
+    /// Relay requires payment before a new connection may perform any action
+    pub payment_required: Option<bool>,
 }
 
 impl RelayInformationDocument {